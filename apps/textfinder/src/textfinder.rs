@@ -1,6 +1,36 @@
-use executive::Executive;
+use executive::{Executive, Parsed};
+use std::ffi::OsString;
 
 fn main() {
+    let mut cl_arguments: Vec<OsString> = std::env::args_os().collect();
+
+    // Mimic command line arguments if nothing is passed
+    if cl_arguments.len() < 2 {
+        cl_arguments.append(&mut vec![
+            "--path".into(),
+            "./".into(),
+            "--pattern".into(),
+            "toml".into(),
+            "--text".into(),
+            "name".into(),
+            "--recurse".into(),
+            "true".into(),
+        ]);
+    }
+
+    let mut ex = Executive::new();
+    match ex.parse_cla(&cl_arguments) {
+        Parsed::Search => {}
+        Parsed::Print(text) => {
+            print!("{}", text);
+            return;
+        }
+        Parsed::UsageError(e) => {
+            eprintln!("error: {}\n\nFor more information, try `--help`", e);
+            std::process::exit(2);
+        }
+    }
+
     println!("=============================");
     println!("===== Text Finder ===========");
 
@@ -12,27 +42,8 @@ fn main() {
 
     println!("=============================");
 
-    let mut cl_arguments: Vec<String> = std::env::args().collect();
-
     println!("{:?}", cl_arguments);
 
-    // Mimic command line arguments if nothing is passed
-    if cl_arguments.len() < 3 {
-        cl_arguments.append(&mut vec![
-            "--path".to_string(),
-            "./".to_string(),
-            "--pattern".to_string(),
-            "toml".to_string(),
-            "--text".to_string(),
-            "name".to_string(),
-            "--recurse".to_string(),
-            "true".to_string(),
-        ]);
-    }
-
-    let mut ex = Executive::new();
-    ex.parse_cla(&cl_arguments);
-
     ex.display_parsed_params();
 
     println!(
@@ -44,6 +55,9 @@ fn main() {
     );
 
     ex.initialize_dir_nav();
-    ex.start_text_finder();
+    if let Err(e) = ex.start_text_finder() {
+        eprintln!("error: {}", e);
+        std::process::exit(1);
+    }
     println!("=============================");
 }
@@ -0,0 +1,89 @@
+use std::io::{self, Error, ErrorKind, Read, Write};
+use std::path::{Path, PathBuf};
+
+pub fn write_u32(out: &mut impl Write, value: u32) -> io::Result<()> {
+    out.write_all(&value.to_le_bytes())
+}
+
+pub fn read_u32(input: &mut impl Read) -> io::Result<u32> {
+    let mut bytes = [0u8; 4];
+    input.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+pub fn write_u64(out: &mut impl Write, value: u64) -> io::Result<()> {
+    out.write_all(&value.to_le_bytes())
+}
+
+pub fn read_u64(input: &mut impl Read) -> io::Result<u64> {
+    let mut bytes = [0u8; 8];
+    input.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+/// Bytes behind their length
+pub fn write_bytes(out: &mut impl Write, bytes: &[u8]) -> io::Result<()> {
+    write_u32(out, bytes.len() as u32)?;
+    out.write_all(bytes)
+}
+
+pub fn read_bytes(input: &mut impl Read) -> io::Result<Vec<u8>> {
+    let len = read_u32(input)? as u64;
+    let mut bytes = Vec::new();
+    input.take(len).read_to_end(&mut bytes)?;
+    if bytes.len() as u64 != len {
+        return Err(Error::new(ErrorKind::UnexpectedEof, "truncated file"));
+    }
+    Ok(bytes)
+}
+
+#[cfg(unix)]
+pub fn path_to_bytes(path: &Path) -> Vec<u8> {
+    use std::os::unix::ffi::OsStrExt;
+    path.as_os_str().as_bytes().to_vec()
+}
+
+#[cfg(unix)]
+pub fn path_from_bytes(bytes: Vec<u8>) -> PathBuf {
+    use std::os::unix::ffi::OsStringExt;
+    PathBuf::from(std::ffi::OsString::from_vec(bytes))
+}
+
+#[cfg(not(unix))]
+pub fn path_to_bytes(path: &Path) -> Vec<u8> {
+    path.to_string_lossy().into_owned().into_bytes()
+}
+
+#[cfg(not(unix))]
+pub fn path_from_bytes(bytes: Vec<u8>) -> PathBuf {
+    PathBuf::from(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let mut out = Vec::new();
+        write_u32(&mut out, 7).unwrap();
+        write_u64(&mut out, u64::MAX).unwrap();
+        write_bytes(&mut out, &path_to_bytes(Path::new("src/main.rs"))).unwrap();
+        assert_eq!(out.len(), 4 + 8 + 4 + 11);
+
+        let mut input = out.as_slice();
+        assert_eq!(read_u32(&mut input).unwrap(), 7);
+        assert_eq!(read_u64(&mut input).unwrap(), u64::MAX);
+        let path = path_from_bytes(read_bytes(&mut input).unwrap());
+        assert_eq!(path, PathBuf::from("src/main.rs"));
+    }
+
+    #[test]
+    fn test_truncated() {
+        let mut out = Vec::new();
+        write_bytes(&mut out, b"abcdef").unwrap();
+        out.truncate(7);
+        let err = read_bytes(&mut out.as_slice()).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::UnexpectedEof);
+    }
+}
@@ -1,11 +1,15 @@
 use cli_parser::*;
 
 fn set_args(app: &mut CliParser) {
-    let cl_arguments: Vec<String> = std::env::args().collect();
+    let cl_arguments: Vec<_> = std::env::args_os().collect();
 
     println!("cl_arguments = {:?}", cl_arguments);
-    app.set_params(&cl_arguments);
-    app.parse_params();
+    if let Err(e) = app
+        .set_params_os(&cl_arguments)
+        .and_then(|_| app.parse_params())
+    {
+        println!("Cannot parse arguments: {}", e);
+    }
 
     if app.get_parsed_params().is_empty() {
         let s = vec![
@@ -17,7 +21,7 @@ fn set_args(app: &mut CliParser) {
         ];
 
         app.set_params(&s);
-        app.parse_params();
+        app.parse_params().unwrap();
     }
 
     app.display_params();
@@ -1,10 +1,34 @@
 use std::collections::HashMap;
+use std::ffi::{OsStr, OsString};
+use std::fs;
+use std::path::Path;
+
+mod config;
+mod error;
+mod spec;
+pub use config::{find_project_config, user_config_path, Source};
+pub use error::CliError;
+pub use spec::{OptionSpec, ValueType};
 
 /** Public struct to parse command line instructions */
 pub struct CliParser {
     args_vec: Vec<String>,
     args_dict: HashMap<String, Vec<String>>,
+    /// Index in `args_vec` of each value in `args_dict`
+    arg_indices: HashMap<String, Vec<Option<usize>>>,
     has_parsed: bool,
+    /// Declared options, any option is accepted while there are none
+    specs: Vec<OptionSpec>,
+    /// Options whose value is their default because they were not given
+    defaulted: Vec<String>,
+    /// Values read from config files and the environment, used for the
+    /// options not given on the command line
+    layers: Vec<(Source, HashMap<String, Vec<String>>)>,
+    /// Where the value of each declared option comes from
+    sources: HashMap<String, Source>,
+    /// Name of the program shown in the usage line
+    program_name: String,
+    help_requested: bool,
 }
 
 impl CliParser {
@@ -12,8 +36,183 @@ impl CliParser {
         CliParser {
             args_vec: Vec::<String>::new(),
             args_dict: HashMap::<String, Vec<String>>::new(),
+            arg_indices: HashMap::<String, Vec<Option<usize>>>::new(),
             has_parsed: false,
+            specs: Vec::<OptionSpec>::new(),
+            defaulted: Vec::<String>::new(),
+            layers: Vec::new(),
+            sources: HashMap::<String, Source>::new(),
+            program_name: String::new(),
+            help_requested: false,
+        }
+    }
+
+    /// Declare an option, once options are declared any other option is
+    /// rejected when parsing
+    pub fn add_option(&mut self, spec: OptionSpec) {
+        self.specs.push(spec);
+    }
+
+    /// Declared options in the order they were added
+    pub fn get_options(&self) -> &[OptionSpec] {
+        &self.specs
+    }
+
+    /// Name of the program for the usage line, the file name of the first
+    /// argument is used if not set
+    pub fn set_program_name(&mut self, name: &str) {
+        self.program_name = name.to_string();
+    }
+
+    /// Check whether `--help` was given, the other arguments are not parsed
+    /// in that case
+    pub fn is_help_requested(&self) -> bool {
+        self.help_requested
+    }
+
+    /// Usage line followed by a description of each declared option
+    pub fn help(&self) -> String {
+        let program = if self.program_name.is_empty() {
+            self.args_vec
+                .first()
+                .and_then(|arg| Path::new(arg).file_name())
+                .map_or("program".to_string(), |name| {
+                    name.to_string_lossy().into_owned()
+                })
+        } else {
+            self.program_name.clone()
+        };
+
+        let mut rows = Vec::<(String, String)>::new();
+        for spec in &self.specs {
+            let mut names = vec![match spec.value_type {
+                ValueType::Bool => format!("--[no-]{}", spec.name),
+                _ => format!("--{}", spec.name),
+            }];
+            names.extend(spec.aliases.iter().map(|alias| format!("--{}", alias)));
+
+            let short = spec
+                .short
+                .map_or("    ".to_string(), |c| format!("-{}, ", c));
+            let mut left = format!("{}{}", short, names.join(", "));
+            if spec.value_type != ValueType::Bool {
+                left = format!("{} {}", left, spec.placeholder());
+            }
+            if spec.repeatable {
+                left.push_str("...");
+            }
+
+            let mut right = spec.help.clone();
+            if spec.required {
+                right.push_str(" [required]");
+            }
+            if let Some(default) = &spec.default {
+                right.push_str(&format!(" [default: {:?}]", default));
+            }
+            rows.push((left, right.trim_start().to_string()));
         }
+        let help = if self.has_help_short() {
+            "-h, --help"
+        } else {
+            "    --help"
+        };
+        rows.push((help.to_string(), "print this help and exit".to_string()));
+
+        let width = rows.iter().map(|(left, _)| left.len()).max().unwrap_or(0);
+
+        let mut usage = format!("Usage: {} [OPTIONS]", program);
+        for spec in self.positionals() {
+            let name = spec.name.to_uppercase();
+            if spec.required {
+                usage.push_str(&format!(" <{}>", name));
+            } else {
+                usage.push_str(&format!(" [{}]", name));
+            }
+        }
+        // only the last one takes more than one value
+        if self
+            .positionals()
+            .last()
+            .is_some_and(|spec| spec.repeatable)
+        {
+            usage.push_str("...");
+        }
+
+        let mut help = format!("{}\n\nOptions:\n", usage);
+        for (left, right) in rows {
+            help.push_str(&format!("  {:width$}  {}\n", left, right, width = width));
+        }
+
+        if let Some(switch) = self
+            .specs
+            .iter()
+            .find(|spec| spec.value_type == ValueType::Bool)
+        {
+            help.push_str(&format!(
+                "\nA switch takes a `true` or `false` right after it as its value, \
+                 like `--{} false`. Give a positional `true` or `false` after `--`.\n",
+                switch.name
+            ));
+        }
+        help
+    }
+
+    /// Declared options taking positional values, in order
+    fn positionals(&self) -> Vec<&OptionSpec> {
+        self.specs.iter().filter(|spec| spec.positional).collect()
+    }
+
+    /// `-h` asks for help unless an option uses it, only once options are
+    /// declared as short options are not known before
+    fn has_help_short(&self) -> bool {
+        !self.specs.is_empty() && !self.specs.iter().any(|spec| spec.short == Some('h'))
+    }
+
+    /// Check whether `--help`, or `-h` if no option claims it, is given in
+    /// place of an option before `--`. As the value of an option, like in
+    /// `--text --help`, it is searched for instead.
+    fn is_help_given(&self) -> bool {
+        let help_short = self.has_help_short();
+        let mut pending = false;
+
+        for arg in self.args_vec.iter().skip(1).take_while(|arg| *arg != "--") {
+            if pending {
+                pending = false;
+            } else if arg == "--help" || (help_short && arg == "-h") {
+                return true;
+            } else {
+                pending = self.takes_next_arg(arg);
+            }
+        }
+
+        false
+    }
+
+    /// Check whether `arg` is an option whose value is the next argument
+    fn takes_next_arg(&self, arg: &str) -> bool {
+        if self.specs.is_empty() {
+            return false;
+        }
+
+        if let Some(long) = arg.strip_prefix("--") {
+            return !long.contains('=')
+                && matches!(
+                    self.resolve_long(&long.to_lowercase(), arg),
+                    Ok(Resolved::Valued(_))
+                );
+        }
+
+        if arg.len() > 1 && arg.starts_with('-') {
+            for (pos, short) in arg.char_indices().skip(1) {
+                match self.resolve_short(short) {
+                    Ok(Resolved::Switch(_)) => {}
+                    Ok(Resolved::Valued(_)) => return pos + short.len_utf8() == arg.len(),
+                    _ => return false,
+                }
+            }
+        }
+
+        false
     }
 
     pub fn set_params(&mut self, params: &[String]) {
@@ -21,6 +220,26 @@ impl CliParser {
         self.has_parsed = false;
     }
 
+    /// Set arguments as given by `std::env::args_os`, all of them have to
+    /// be valid UTF-8
+    pub fn set_params_os<S: AsRef<OsStr>>(&mut self, params: &[S]) -> Result<(), CliError> {
+        let mut args = Vec::<String>::new();
+        for (index, param) in params.iter().enumerate() {
+            match param.as_ref().to_str() {
+                Some(arg) => args.push(arg.to_string()),
+                None => {
+                    return Err(CliError::BadUtf8 {
+                        index,
+                        arg: param.as_ref().to_string_lossy().into_owned(),
+                    })
+                }
+            }
+        }
+
+        self.set_params(&args);
+        Ok(())
+    }
+
     pub fn display_params(&self) {
         println!("\n=============================");
         println!("Command line arguments = {:?}", self.args_vec);
@@ -31,56 +250,443 @@ impl CliParser {
         &self.args_vec
     }
 
-    pub fn parse_params(&mut self) {
+    pub fn parse_params(&mut self) -> Result<(), CliError> {
         if self.has_parsed {
             println!("Have parsed the current arguments already!");
-            return;
+            return Ok(());
         }
 
         // Arguments should be of the form `binary name`, `--key`, `value`
         // Example:
         //      1. ./cliparser --path ./
         //      2. cargo run cli_parser --path ./
-        if self.args_vec.len() < 3 {
-            return;
+        //      3. ./cliparser -r --text=name --no-decompress
+        //      4. ./cliparser name src -- --no-decompress
+        if self.is_help_given() {
+            self.help_requested = true;
+            return Ok(());
         }
 
-        // remove first argument which is self
-        self.args_vec.remove(0);
-
-        if self.args_vec[0][0..2] != *"--" && self.args_vec[1][0..2] != *"--" {
-            std::panic!("Arguments should be in the format `--key` `value`");
+        if self.args_vec.len() >= 2 {
+            self.parse_args()?;
         }
 
-        // In case of, `cargo run cli_parser --path ./`, when it reaches here
-        // `self.args_vec[0]` is `cli_parser`
-        if self.args_vec[0][0..2] != *"--" {
-            self.args_vec.remove(0);
+        self.apply_specs()?;
+        self.has_parsed = true;
+        Ok(())
+    }
+
+    fn parse_args(&mut self) -> Result<(), CliError> {
+        // skip first argument which is self, it names the program in help
+        let mut args = self.args_vec[1..].to_vec();
+        let mut offset = 1;
+
+        // Without positional options leading values are not expected
+        if self.positionals().is_empty() {
+            if args.len() > 1 && !args[0].starts_with('-') && !args[1].starts_with('-') {
+                // Arguments should be in the format `--key` `value`
+                return Err(CliError::UnexpectedValue {
+                    arg: args[0].clone(),
+                });
+            }
+
+            // In case of, `cargo run cli_parser --path ./`, when it reaches here
+            // `args[0]` is `cli_parser`
+            if args.len() > 1 && !args[0].starts_with('-') {
+                args.remove(0);
+                offset += 1;
+            }
         }
 
-        let mut arg_str = "";
+        // option the following values belong to
+        let mut key = String::new();
+        // the option was just given and its value is the next argument,
+        // whatever it looks like
+        let mut pending = false;
+        // boolean switch that may still be followed by `true` or `false`
+        let mut switch: Option<String> = None;
+        // next positional option to take a value
+        let mut positional = 0;
+        // options given by name
+        let mut named = Vec::<String>::new();
+        // everything after `--` is a value
+        let mut terminated = false;
 
-        for arg in &self.args_vec {
-            if arg[0..2] == *"--" {
-                // key cannot be empty
-                if arg.len() < 2 {
-                    std::panic!("Key cannot be empty `--`, should be of format `--key`");
+        for (index, arg) in args.into_iter().enumerate() {
+            let index = Some(index + offset);
+
+            if pending && !self.specs.is_empty() {
+                pending = false;
+                self.insert_value(&key, &arg, index);
+            } else if terminated {
+                self.add_value(&key, &arg, index, &mut positional, &named)?;
+            } else if arg == "--" {
+                terminated = true;
+                switch = None;
+            } else if let Some(long) = arg.strip_prefix("--") {
+                let (name, value) = match long.split_once('=') {
+                    Some((name, value)) => (name, Some(value)),
+                    None => (long, None),
+                };
+
+                let option = self.resolve_long(&name.to_lowercase(), &arg)?;
+                key = String::new();
+                pending = false;
+                switch = None;
+
+                match (option, value) {
+                    (Resolved::Negated(name), None) => self.set_value(&name, "false", index),
+                    (Resolved::Negated(_), Some(value)) => {
+                        return Err(CliError::InvalidValue {
+                            option: format!("--{}", name),
+                            value: value.to_string(),
+                            expected: "no value".to_string(),
+                        })
+                    }
+                    (Resolved::Switch(name), Some(value)) => self.set_value(&name, value, index),
+                    (Resolved::Switch(name), None) => {
+                        self.set_value(&name, "true", index);
+                        switch = Some(name);
+                    }
+                    (Resolved::Valued(name), Some(value)) => {
+                        named.push(name.clone());
+                        self.insert_value(&name, value, index);
+                    }
+                    (Resolved::Valued(name), None) => {
+                        named.push(name.clone());
+                        self.args_dict.entry(name.clone()).or_default();
+                        key = name;
+                        pending = true;
+                    }
                 }
+            } else if arg.len() > 1 && arg.starts_with('-') && !self.specs.is_empty() {
+                // cluster of short options like `-rn`, the first one taking a
+                // value ends it and takes the rest, `-l5`, or the next argument
+                key = String::new();
+                pending = false;
+                switch = None;
 
-                arg_str = &arg[2..];
+                for (pos, short) in arg.char_indices().skip(1) {
+                    match self.resolve_short(short)? {
+                        Resolved::Switch(name) => {
+                            self.set_value(&name, "true", index);
+                            switch = Some(name);
+                        }
+                        Resolved::Valued(name) => {
+                            switch = None;
+                            named.push(name.clone());
+                            self.args_dict.entry(name.clone()).or_default();
 
-                self.args_dict
-                    .entry(arg[2..].to_string().to_lowercase())
-                    .or_insert_with(Vec::<String>::new);
+                            let rest = &arg[pos + short.len_utf8()..];
+                            let rest = rest.strip_prefix('=').unwrap_or(rest);
+                            if rest.is_empty() {
+                                key = name;
+                                pending = true;
+                            } else {
+                                self.insert_value(&name, rest, index);
+                            }
+                            break;
+                        }
+                        Resolved::Negated(_) => unreachable!(),
+                    }
+                }
             } else {
-                if arg[0..1] == *"-" {
-                    std::panic!("Value cannot begin with reserved char `-`");
+                // `--recurse false` still works for switches
+                if let Some(name) = switch.take() {
+                    if arg == "true" || arg == "false" {
+                        self.set_value(&name, &arg, index);
+                        continue;
+                    }
                 }
-                CliParser::insert_if_not_exist(&mut self.args_dict, &arg_str.to_lowercase(), arg);
+
+                // a lone `-` is the usual name for stdin
+                if arg.starts_with('-') && arg != "-" {
+                    return Err(self.unknown_option(&arg));
+                }
+
+                pending = false;
+                self.add_value(&key, &arg, index, &mut positional, &named)?;
             }
         }
 
-        self.has_parsed = true;
+        Ok(())
+    }
+
+    /// Add a value that does not directly follow an option, it goes to the
+    /// next positional option if there is one left and otherwise to the
+    /// last option given
+    fn add_value(
+        &mut self,
+        key: &str,
+        arg: &str,
+        index: Option<usize>,
+        positional: &mut usize,
+        named: &[String],
+    ) -> Result<(), CliError> {
+        let positionals = self.positionals();
+        // like `grep -e`, an option given by name does not take positional
+        // values, they go to the next positional option
+        while positionals
+            .get(*positional)
+            .is_some_and(|spec| named.contains(&spec.name))
+        {
+            *positional += 1;
+        }
+
+        if let Some(spec) = positionals.get(*positional) {
+            // the last positional option takes the rest if it is repeatable
+            if !spec.repeatable || *positional + 1 < positionals.len() {
+                *positional += 1;
+            }
+            let name = spec.name.clone();
+            self.insert_value(&name, arg, index);
+        } else if !key.is_empty() {
+            self.insert_value(key, arg, index);
+        } else {
+            return Err(CliError::UnexpectedValue {
+                arg: arg.to_string(),
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Error for an unknown option, suggesting the declared option closest
+    /// to it if there is one close enough
+    fn unknown_option(&self, arg: &str) -> CliError {
+        let key = arg.trim_start_matches('-').to_lowercase();
+        let suggestion = self
+            .specs
+            .iter()
+            .map(|spec| (error::edit_distance(&key, &spec.name), &spec.name))
+            .filter(|(distance, _)| *distance <= 2 && *distance < key.len())
+            .min()
+            .map(|(_, name)| format!("--{}", name));
+
+        CliError::UnknownOption {
+            arg: arg.to_string(),
+            suggestion,
+        }
+    }
+
+    /// Options of the user's config file, they override the defaults
+    pub fn add_user_config(&mut self, path: &Path) -> Result<(), CliError> {
+        self.add_config_file(path, Source::UserConfig(path.to_path_buf()))
+    }
+
+    /// Options of the project's config file, they override the user's
+    pub fn add_project_config(&mut self, path: &Path) -> Result<(), CliError> {
+        self.add_config_file(path, Source::ProjectConfig(path.to_path_buf()))
+    }
+
+    fn add_config_file(&mut self, path: &Path, source: Source) -> Result<(), CliError> {
+        let error = |line, message| CliError::Config {
+            path: path.to_path_buf(),
+            line,
+            message,
+        };
+
+        let text = fs::read_to_string(path).map_err(|e| error(0, e.to_string()))?;
+        let entries = config::parse_toml(&text).map_err(|(line, message)| error(line, message))?;
+
+        let mut values = HashMap::<String, Vec<String>>::new();
+        for entry in entries {
+            let name = self
+                .check_layer_values(&entry.key, &entry.values)
+                .map_err(|message| error(entry.line, message))?;
+            values.insert(name, entry.values);
+        }
+
+        self.layers.push((source, values));
+        Ok(())
+    }
+
+    /// Options of environment variables named after them, `PREFIX_LIMIT`
+    /// for `limit` and `PREFIX_FILES_FROM` for `files-from`, each with a
+    /// single value. They override the config files.
+    pub fn add_env(&mut self, prefix: &str) -> Result<(), CliError> {
+        self.add_vars(prefix, std::env::vars_os())
+    }
+
+    /// Same as `add_env` for the given variables instead of the ones of the
+    /// process
+    pub fn add_vars(
+        &mut self,
+        prefix: &str,
+        vars: impl IntoIterator<Item = (OsString, OsString)>,
+    ) -> Result<(), CliError> {
+        let mut vars: HashMap<OsString, OsString> = vars.into_iter().collect();
+
+        for spec in &self.specs {
+            let var = format!("{}_{}", prefix, spec.name.to_uppercase().replace('-', "_"));
+            let value = match vars.remove(OsStr::new(&var)) {
+                Some(value) => value
+                    .into_string()
+                    .map_err(|value| CliError::InvalidValue {
+                        option: var.clone(),
+                        value: value.to_string_lossy().into_owned(),
+                        expected: "valid UTF-8".to_string(),
+                    })?,
+                None => continue,
+            };
+
+            if let Err(expected) = spec.check(&value) {
+                return Err(CliError::InvalidValue {
+                    option: var,
+                    value,
+                    expected,
+                });
+            }
+
+            let values = HashMap::from([(spec.name.clone(), vec![value])]);
+            self.layers.push((Source::Environment(var), values));
+        }
+
+        Ok(())
+    }
+
+    /// Check values of `key` read from a config file against the declared
+    /// options, the name of the option or what is wrong
+    fn check_layer_values(&self, key: &str, values: &[String]) -> Result<String, String> {
+        let spec = match self
+            .specs
+            .iter()
+            .find(|spec| spec.matches(&key.to_lowercase()))
+        {
+            Some(spec) => spec,
+            None => return Err(self.unknown_option(key).to_string()),
+        };
+
+        if values.is_empty() {
+            return Err(format!("`{}` needs a value", key));
+        }
+
+        if values.len() > 1 && !spec.repeatable {
+            return Err(format!("`{}` takes a single value", key));
+        }
+
+        for value in values {
+            if let Err(expected) = spec.check(value) {
+                return Err(format!(
+                    "invalid value `{}` for `{}`, expected {}",
+                    value, key, expected
+                ));
+            }
+        }
+
+        Ok(spec.name.clone())
+    }
+
+    /// Find the option `--key` refers to, any option is valid while none are
+    /// declared. Boolean options are switches and can be negated with
+    /// `--no-key`.
+    fn resolve_long(&self, key: &str, arg: &str) -> Result<Resolved, CliError> {
+        if self.specs.is_empty() {
+            return Ok(Resolved::Valued(key.to_string()));
+        }
+
+        if let Some(spec) = self.specs.iter().find(|spec| spec.matches(key)) {
+            return Ok(match spec.value_type {
+                ValueType::Bool => Resolved::Switch(spec.name.clone()),
+                _ => Resolved::Valued(spec.name.clone()),
+            });
+        }
+
+        let negated = key.strip_prefix("no-").and_then(|key| {
+            self.specs
+                .iter()
+                .find(|spec| spec.matches(key) && spec.value_type == ValueType::Bool)
+        });
+        match negated {
+            Some(spec) => Ok(Resolved::Negated(spec.name.clone())),
+            None => Err(self.unknown_option(arg.split('=').next().unwrap_or(arg))),
+        }
+    }
+
+    /// Find the option `-c` refers to
+    fn resolve_short(&self, short: char) -> Result<Resolved, CliError> {
+        match self.specs.iter().find(|spec| spec.short == Some(short)) {
+            Some(spec) if spec.value_type == ValueType::Bool => {
+                Ok(Resolved::Switch(spec.name.clone()))
+            }
+            Some(spec) => Ok(Resolved::Valued(spec.name.clone())),
+            None => Err(CliError::UnknownOption {
+                arg: format!("-{}", short),
+                suggestion: None,
+            }),
+        }
+    }
+
+    /// Replace the value of a single valued option, the last one given wins
+    fn set_value(&mut self, key: &str, value: &str, index: Option<usize>) {
+        self.args_dict
+            .insert(key.to_string(), vec![value.to_string()]);
+        self.arg_indices.insert(key.to_string(), vec![index]);
+    }
+
+    /// Check the parsed values against the declared options and fill in
+    /// the ones not given from the config files, the environment or their
+    /// defaults
+    fn apply_specs(&mut self) -> Result<(), CliError> {
+        for spec in &self.specs {
+            let values = match self.args_dict.get(&spec.name) {
+                Some(values) => values,
+                None => {
+                    // the last of the most important sources setting it
+                    let layer = self
+                        .layers
+                        .iter()
+                        .filter(|(_, values)| values.contains_key(&spec.name))
+                        .max_by_key(|(source, _)| source);
+
+                    if let Some((source, values)) = layer {
+                        let values = values[&spec.name].clone();
+                        self.arg_indices
+                            .insert(spec.name.clone(), vec![None; values.len()]);
+                        self.args_dict.insert(spec.name.clone(), values);
+                        self.sources.insert(spec.name.clone(), source.clone());
+                    } else if let Some(default) = &spec.default {
+                        self.args_dict
+                            .insert(spec.name.clone(), vec![default.clone()]);
+                        self.arg_indices.insert(spec.name.clone(), vec![None]);
+                        self.defaulted.push(spec.name.clone());
+                        self.sources.insert(spec.name.clone(), Source::Default);
+                    } else if spec.required {
+                        return Err(CliError::MissingValue {
+                            option: format!("--{}", spec.name),
+                        });
+                    }
+                    continue;
+                }
+            };
+            self.sources.insert(spec.name.clone(), Source::CommandLine);
+
+            if values.is_empty() {
+                return Err(CliError::MissingValue {
+                    option: format!("--{}", spec.name),
+                });
+            }
+
+            if values.len() > 1 && !spec.repeatable {
+                return Err(CliError::InvalidValue {
+                    option: format!("--{}", spec.name),
+                    value: values[1].clone(),
+                    expected: "a single value".to_string(),
+                });
+            }
+
+            for value in values {
+                if let Err(expected) = spec.check(value) {
+                    return Err(CliError::InvalidValue {
+                        option: format!("--{}", spec.name),
+                        value: value.clone(),
+                        expected,
+                    });
+                }
+            }
+        }
+
+        Ok(())
     }
 
     pub fn display_parsed_params(&self) {
@@ -98,25 +704,25 @@ impl CliParser {
 
     // Allow to set/add to `key` a new `value`
     pub fn add_params_key_value(&mut self, key: &str, value: &str) {
-        // If not exist, create a dummy entry to avoid remove returning `None`
-        self.args_dict
-            .entry(key.to_string().to_lowercase())
-            .or_insert_with(Vec::<String>::new);
-
-        CliParser::insert_if_not_exist(&mut self.args_dict, &key.to_lowercase(), value);
+        self.insert_value(&key.to_lowercase(), value, None);
     }
 
-    fn insert_if_not_exist(dict: &mut HashMap<String, Vec<String>>, key: &str, value: &str) {
-        let mut v = dict.remove(&key.to_string()).unwrap();
+    /// Append a value to an option, unless the option drops duplicates and
+    /// has it already
+    fn insert_value(&mut self, key: &str, value: &str, index: Option<usize>) {
+        let dedup = self.specs.iter().any(|spec| spec.name == key && spec.dedup);
+        let values = self.args_dict.entry(key.to_string()).or_default();
 
-        match v.binary_search(&value.to_string()) {
-            Ok(_) => {
-                println!("\n`--{} {}` not added as it already exists", &*key, &*value)
-            }
-            Err(pos) => v.insert(pos, value.to_string()),
+        if dedup && values.iter().any(|v| v == value) {
+            println!("\n`--{} {}` not added as it already exists", key, value);
+            return;
         }
 
-        dict.insert(key.to_string(), v);
+        values.push(value.to_string());
+        self.arg_indices
+            .entry(key.to_string())
+            .or_default()
+            .push(index);
     }
 
     pub fn get_valuesvec_for_key(&self, key: &str) -> core::option::Option<&Vec<String>> {
@@ -126,6 +732,99 @@ impl CliParser {
 
         None
     }
+
+    /// Check whether an option was given on the command line, in a config
+    /// file or the environment, as opposed to not being there at all or
+    /// having its default value
+    pub fn is_given(&self, key: &str) -> bool {
+        self.args_dict.contains_key(key) && !self.defaulted.iter().any(|name| name == key)
+    }
+
+    /// Value of a boolean option, `false` when not given
+    pub fn get_bool(&self, key: &str) -> bool {
+        self.get_string(key) == Some("true")
+    }
+
+    /// Value of an integer option
+    pub fn get_integer(&self, key: &str) -> Option<usize> {
+        self.get_string(key).and_then(|value| value.parse().ok())
+    }
+
+    /// First value of an option
+    pub fn get_string(&self, key: &str) -> Option<&str> {
+        self.get_strings(key).first().map(|value| value.as_str())
+    }
+
+    /// All values of an option, empty when not given
+    pub fn get_strings(&self, key: &str) -> &[String] {
+        self.args_dict
+            .get(key)
+            .map_or(&[], |values| values.as_slice())
+    }
+
+    /// Where the value of a declared option comes from, once parsed
+    pub fn get_source(&self, key: &str) -> Option<&Source> {
+        self.sources.get(key)
+    }
+
+    /// Value of each declared option and where it comes from, written as
+    /// a config file
+    pub fn effective_config(&self) -> String {
+        let mut rows = Vec::<(String, String)>::new();
+        for spec in &self.specs {
+            let values = self.get_strings(&spec.name);
+            if values.is_empty() {
+                continue;
+            }
+
+            let bare = matches!(spec.value_type, ValueType::Bool | ValueType::Integer);
+            let mut values: Vec<String> = values
+                .iter()
+                .map(|value| config::toml_value(value, bare))
+                .collect();
+            let value = if spec.repeatable {
+                format!("[{}]", values.join(", "))
+            } else {
+                values.remove(0)
+            };
+
+            let source = self
+                .get_source(&spec.name)
+                .map_or("not parsed".to_string(), |source| source.to_string());
+            rows.push((format!("{} = {}", spec.name, value), source));
+        }
+
+        let width = rows.iter().map(|(left, _)| left.len()).max().unwrap_or(0);
+
+        let mut config = String::new();
+        for (left, source) in rows {
+            config.push_str(&format!("{:width$}  # {}\n", left, source, width = width));
+        }
+        config
+    }
+
+    /// Index in the arguments of each value of an option, in the order of
+    /// `get_strings`, `None` for defaults and values added after parsing
+    pub fn get_indices(&self, key: &str) -> &[Option<usize>] {
+        self.arg_indices
+            .get(key)
+            .map_or(&[], |indices| indices.as_slice())
+    }
+
+    /// Value of a path option
+    pub fn get_path(&self, key: &str) -> Option<&Path> {
+        self.get_string(key).map(Path::new)
+    }
+}
+
+/// Option named on the command line with what it expects
+enum Resolved {
+    /// Option taking values
+    Valued(String),
+    /// Boolean option, `true` unless a value is given
+    Switch(String),
+    /// Boolean option given as `--no-key`
+    Negated(String),
 }
 
 impl Default for CliParser {
@@ -160,7 +859,7 @@ mod tests {
 
         let cl_arguments = Vec::<String>::new();
         app.set_params(&cl_arguments);
-        app.parse_params();
+        app.parse_params().unwrap();
         assert!(app.get_parsed_params().is_empty());
     }
 
@@ -178,17 +877,16 @@ mod tests {
 
         app.set_params(&s);
         app.display_params();
-        app.parse_params();
+        app.parse_params().unwrap();
         app.display_parsed_params();
 
         assert_eq!(app.get_parsed_params().len(), 2);
 
-        app.parse_params();
+        app.parse_params().unwrap();
         assert_eq!(app.get_parsed_params().len(), 2);
     }
 
     #[test]
-    #[should_panic]
     fn test_parsing_invalid_key() {
         let mut app = CliParser::new();
 
@@ -203,11 +901,15 @@ mod tests {
         app.set_params(&s);
         app.display_params();
 
-        app.parse_params();
+        assert_eq!(
+            app.parse_params(),
+            Err(CliError::UnexpectedValue {
+                arg: "path".to_string()
+            })
+        );
     }
 
     #[test]
-    #[should_panic]
     fn test_parsing_invalid_value() {
         let mut app = CliParser::new();
 
@@ -221,7 +923,13 @@ mod tests {
 
         app.set_params(&s);
         app.display_params();
-        app.parse_params();
+        assert_eq!(
+            app.parse_params(),
+            Err(CliError::UnknownOption {
+                arg: "-version".to_string(),
+                suggestion: None
+            })
+        );
     }
 
     #[test]
@@ -244,11 +952,11 @@ mod tests {
 
         app.set_params(&s);
         app.display_params();
-        app.parse_params();
+        app.parse_params().unwrap();
         app.display_parsed_params();
 
         assert_eq!(app.get_parsed_params().len(), 2);
-        assert_eq!(app.get_valuesvec_for_key("path").unwrap().len(), 2);
+        assert_eq!(app.get_strings("path"), ["./", "./", "../"]);
         assert_eq!(app.get_valuesvec_for_key("version").unwrap().len(), 2);
     }
 
@@ -266,7 +974,7 @@ mod tests {
 
         app.set_params(&s);
         app.display_params();
-        app.parse_params();
+        app.parse_params().unwrap();
         app.display_parsed_params();
         assert_eq!(app.get_parsed_params().len(), 2);
 
@@ -276,4 +984,586 @@ mod tests {
         assert_eq!(app.get_valuesvec_for_key("path").unwrap().len(), 2);
         assert_eq!(app.get_valuesvec_for_key("version").unwrap().len(), 1);
     }
+
+    fn spec_parser() -> CliParser {
+        let mut app = CliParser::new();
+        app.add_option(
+            OptionSpec::new("path")
+                .alias("dir")
+                .short('p')
+                .value_type(ValueType::Path)
+                .default_value("./")
+                .repeatable(),
+        );
+        app.add_option(
+            OptionSpec::new("recurse")
+                .short('r')
+                .value_type(ValueType::Bool)
+                .default_value("false"),
+        );
+        app.add_option(
+            OptionSpec::new("limit")
+                .short('l')
+                .value_type(ValueType::Integer),
+        );
+        app.add_option(
+            OptionSpec::new("decompress")
+                .short('z')
+                .value_type(ValueType::Bool)
+                .default_value("true"),
+        );
+        app.add_option(OptionSpec::new("text").required());
+        app
+    }
+
+    #[test]
+    fn test_option_spec() {
+        let mut app = spec_parser();
+
+        let s = vec![
+            "cliparser".to_string(),
+            "--dir".to_string(),
+            "src".to_string(),
+            "--path".to_string(),
+            "apps".to_string(),
+            "--limit".to_string(),
+            "3".to_string(),
+            "--Text".to_string(),
+            "name".to_string(),
+        ];
+
+        app.set_params(&s);
+        app.parse_params().unwrap();
+        app.display_parsed_params();
+
+        assert_eq!(app.get_strings("path"), ["src", "apps"]);
+        assert!(app.get_valuesvec_for_key("dir").is_none());
+        assert_eq!(app.get_integer("limit"), Some(3));
+        assert_eq!(app.get_string("text"), Some("name"));
+        assert!(!app.get_bool("recurse"));
+        assert!(app.is_given("path"));
+        assert!(!app.is_given("recurse"));
+        assert_eq!(app.get_path("path"), Some(Path::new("src")));
+        assert!(app.get_strings("missing").is_empty());
+    }
+
+    #[test]
+    fn test_option_spec_unknown() {
+        let mut app = spec_parser();
+
+        let s = vec![
+            "cliparser".to_string(),
+            "--text".to_string(),
+            "name".to_string(),
+            "--version".to_string(),
+            "0.1".to_string(),
+        ];
+
+        app.set_params(&s);
+        assert_eq!(
+            app.parse_params(),
+            Err(CliError::UnknownOption {
+                arg: "--version".to_string(),
+                suggestion: None
+            })
+        );
+
+        let mut app = spec_parser();
+        let error = parse(&mut app, &["--text", "name", "--recurce"]).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "unknown option `--recurce`, did you mean `--recurse`?"
+        );
+    }
+
+    #[test]
+    fn test_option_spec_invalid() {
+        let mut app = spec_parser();
+
+        let s = vec![
+            "cliparser".to_string(),
+            "--text".to_string(),
+            "name".to_string(),
+            "--recurse=yes".to_string(),
+        ];
+
+        app.set_params(&s);
+        let error = app.parse_params().unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "invalid value `yes` for `--recurse`, expected either `true` or `false`"
+        );
+
+        let mut app = spec_parser();
+        let error = parse(&mut app, &["--text", "name", "--limit"]).unwrap_err();
+        assert_eq!(
+            error,
+            CliError::MissingValue {
+                option: "--limit".to_string()
+            }
+        );
+
+        let mut app = spec_parser();
+        let error = parse(&mut app, &["--text", "name", "--no-recurse=true"]).unwrap_err();
+        assert!(matches!(error, CliError::InvalidValue { .. }));
+    }
+
+    #[test]
+    fn test_option_spec_required() {
+        let mut app = spec_parser();
+        app.set_params(&Vec::<String>::new());
+        assert_eq!(
+            app.parse_params(),
+            Err(CliError::MissingValue {
+                option: "--text".to_string()
+            })
+        );
+        assert_eq!(
+            app.parse_params().unwrap_err().to_string(),
+            "`--text` needs a value"
+        );
+    }
+
+    #[test]
+    fn test_option_spec_choices() {
+        let spec = || {
+            let mut app = CliParser::new();
+            app.add_option(
+                OptionSpec::new("traversal")
+                    .choices(&["depth", "breadth"])
+                    .default_value("depth"),
+            );
+            app
+        };
+
+        let mut app = spec();
+        parse(&mut app, &["--traversal", "breadth"]).unwrap();
+        assert_eq!(app.get_string("traversal"), Some("breadth"));
+
+        let mut app = spec();
+        let error = parse(&mut app, &["--traversal=wide"]).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "invalid value `wide` for `--traversal`, expected one of `depth`, `breadth`"
+        );
+
+        let mut app = spec();
+        let vars = [(OsString::from("APP_TRAVERSAL"), OsString::from("wide"))];
+        assert!(app.add_vars("APP", vars).is_err());
+
+        let help = spec().help();
+        assert!(help.contains("--traversal <depth|breadth>"));
+    }
+
+    #[test]
+    fn test_help() {
+        let mut app = spec_parser();
+
+        let s = vec![
+            "target/debug/cliparser".to_string(),
+            "--recurse".to_string(),
+            "maybe".to_string(),
+            "--help".to_string(),
+        ];
+
+        app.set_params(&s);
+        app.parse_params().unwrap();
+        assert!(app.is_help_requested());
+        assert!(app.get_parsed_params().is_empty());
+
+        let help = app.help();
+        println!("{}", help);
+
+        let lines: Vec<&str> = help.lines().collect();
+        assert_eq!(lines[0], "Usage: cliparser [OPTIONS]");
+        assert_eq!(lines[3], "  -p, --path, --dir <PATH>...  [default: \"./\"]");
+        assert_eq!(
+            lines[4],
+            "  -r, --[no-]recurse           [default: \"false\"]"
+        );
+        assert_eq!(
+            lines[6],
+            "  -z, --[no-]decompress        [default: \"true\"]"
+        );
+        assert_eq!(lines[7], "      --text <TEXT>            [required]");
+        assert_eq!(
+            lines[8],
+            "  -h, --help                   print this help and exit"
+        );
+
+        app.set_program_name("textfinder");
+        assert!(app.help().starts_with("Usage: textfinder [OPTIONS]"));
+    }
+
+    fn parse(app: &mut CliParser, args: &[&str]) -> Result<(), CliError> {
+        let mut s = vec!["cliparser".to_string()];
+        s.extend(args.iter().map(|arg| arg.to_string()));
+        app.set_params(&s);
+        app.parse_params()
+    }
+
+    #[test]
+    fn test_help_as_value() {
+        // the value of an option is searched for, not taken as help
+        let mut app = spec_parser();
+        parse(&mut app, &["--text", "--help"]).unwrap();
+        assert!(!app.is_help_requested());
+        assert_eq!(app.get_string("text"), Some("--help"));
+
+        let mut app = spec_parser();
+        parse(&mut app, &["-rp", "-h", "--text", "-h"]).unwrap();
+        assert!(!app.is_help_requested());
+        assert_eq!(app.get_strings("path"), ["-h"]);
+        assert_eq!(app.get_string("text"), Some("-h"));
+
+        // in place of an option it is help, after switches and `key=value`
+        for args in [
+            &["--text", "name", "-h"][..],
+            &["--recurse", "--help"],
+            &["--text=name", "-r", "-h"],
+            &["--limit", "1", "--help", "--", "x"],
+        ] {
+            let mut app = spec_parser();
+            parse(&mut app, args).unwrap();
+            assert!(app.is_help_requested(), "{:?}", args);
+        }
+    }
+
+    #[test]
+    fn test_short_options() {
+        let mut app = spec_parser();
+        parse(
+            &mut app,
+            &["-rl5", "-p", "src", "-p=apps", "--text", "name"],
+        )
+        .unwrap();
+        assert!(app.get_bool("recurse"));
+        assert_eq!(app.get_integer("limit"), Some(5));
+        assert_eq!(app.get_strings("path"), ["src", "apps"]);
+
+        let mut app = spec_parser();
+        parse(&mut app, &["-zr", "false", "-l", "7", "--text=name"]).unwrap();
+        assert!(app.get_bool("decompress"));
+        assert!(!app.get_bool("recurse"));
+        assert_eq!(app.get_integer("limit"), Some(7));
+    }
+
+    #[test]
+    fn test_key_value_and_switches() {
+        let mut app = spec_parser();
+        parse(
+            &mut app,
+            &["--text=a=b", "--recurse", "--no-decompress", "--limit=2"],
+        )
+        .unwrap();
+        assert_eq!(app.get_string("text"), Some("a=b"));
+        assert!(app.get_bool("recurse"));
+        assert!(!app.get_bool("decompress"));
+        assert_eq!(app.get_integer("limit"), Some(2));
+
+        // the last value of a switch wins
+        let mut app = spec_parser();
+        parse(
+            &mut app,
+            &[
+                "--text",
+                "x",
+                "--recurse",
+                "false",
+                "--recurse=true",
+                "--no-recurse",
+            ],
+        )
+        .unwrap();
+        assert!(!app.get_bool("recurse"));
+        assert_eq!(app.get_strings("recurse").len(), 1);
+
+        // `true` after a switch is its value, not the value of what follows
+        let mut app = spec_parser();
+        parse(&mut app, &["--recurse", "true", "--text", "false"]).unwrap();
+        assert!(app.get_bool("recurse"));
+        assert_eq!(app.get_string("text"), Some("false"));
+        assert!(app.help().contains("A switch takes a `true` or `false`"));
+    }
+
+    #[test]
+    fn test_short_options_unknown() {
+        let mut app = spec_parser();
+        assert_eq!(
+            parse(&mut app, &["--text", "name", "-rx"]),
+            Err(CliError::UnknownOption {
+                arg: "-x".to_string(),
+                suggestion: None
+            })
+        );
+    }
+
+    #[test]
+    fn test_positionals() {
+        let positionals = || {
+            let mut app = CliParser::new();
+            app.add_option(OptionSpec::new("text").positional().required());
+            app.add_option(
+                OptionSpec::new("recurse")
+                    .short('r')
+                    .value_type(ValueType::Bool),
+            );
+            app.add_option(OptionSpec::new("limit").value_type(ValueType::Integer));
+            app.add_option(OptionSpec::new("pattern").positional().repeatable());
+            app
+        };
+
+        let mut app = positionals();
+        parse(&mut app, &["name", "-r", "*.rs", "--limit", "2", "*.toml"]).unwrap();
+        assert_eq!(app.get_string("text"), Some("name"));
+        assert_eq!(app.get_strings("pattern"), ["*.rs", "*.toml"]);
+        assert!(app.get_bool("recurse"));
+        assert_eq!(app.get_integer("limit"), Some(2));
+
+        assert!(app
+            .help()
+            .starts_with("Usage: cliparser [OPTIONS] <TEXT> [PATTERN]..."));
+
+        // a switch takes `true` and `false` unless `--` comes first
+        let mut app = positionals();
+        parse(&mut app, &["name", "-r", "false"]).unwrap();
+        assert!(!app.get_bool("recurse"));
+        assert!(app.get_strings("pattern").is_empty());
+
+        let mut app = positionals();
+        parse(&mut app, &["name", "-r", "--", "false"]).unwrap();
+        assert!(app.get_bool("recurse"));
+        assert_eq!(app.get_strings("pattern"), ["false"]);
+        assert!(app
+            .help()
+            .contains("positional `true` or `false` after `--`"));
+
+        // `--text` given by name leaves the values to `pattern`
+        let mut app = CliParser::new();
+        app.add_option(OptionSpec::new("text").positional().repeatable());
+        app.add_option(OptionSpec::new("pattern").positional().repeatable());
+        parse(&mut app, &["--text", "a", "*.rs", "--text=b", "*.toml"]).unwrap();
+        assert_eq!(app.get_strings("text"), ["a", "b"]);
+        assert_eq!(app.get_strings("pattern"), ["*.rs", "*.toml"]);
+        assert!(app
+            .help()
+            .starts_with("Usage: cliparser [OPTIONS] [TEXT] [PATTERN]...\n"));
+
+        // a single positional option does not take a second value
+        let mut app = CliParser::new();
+        app.add_option(OptionSpec::new("text").positional());
+        assert_eq!(
+            parse(&mut app, &["a", "b"]),
+            Err(CliError::UnexpectedValue {
+                arg: "b".to_string()
+            })
+        );
+    }
+
+    #[test]
+    fn test_dash_values() {
+        let mut app = spec_parser();
+        assert_eq!(
+            parse(&mut app, &["--text", "--verbose", "-l", "-1"]),
+            Err(CliError::InvalidValue {
+                option: "--limit".to_string(),
+                value: "-1".to_string(),
+                expected: "a positive number".to_string()
+            })
+        );
+        assert_eq!(app.get_string("text"), Some("--verbose"));
+
+        let mut app = spec_parser();
+        parse(&mut app, &["--text", "-foo", "--dir", "-", "-r"]).unwrap();
+        assert_eq!(app.get_string("text"), Some("-foo"));
+        assert_eq!(app.get_strings("path"), ["-"]);
+        assert!(app.get_bool("recurse"));
+
+        // everything after `--` is a value of a positional option
+        let mut app = spec_parser();
+        app.add_option(OptionSpec::new("pattern").positional().repeatable());
+        parse(&mut app, &["--text", "x", "--", "-r", "--help", "--"]).unwrap();
+        assert_eq!(app.get_strings("pattern"), ["-r", "--help", "--"]);
+        assert!(!app.get_bool("recurse"));
+
+        let mut app = spec_parser();
+        assert_eq!(
+            parse(&mut app, &["--", "--text", "x"]),
+            Err(CliError::UnexpectedValue {
+                arg: "--text".to_string()
+            })
+        );
+    }
+
+    #[test]
+    fn test_order_and_indices() {
+        let mut app = spec_parser();
+        app.add_option(OptionSpec::new("exclude").short('x').repeatable().dedup());
+        parse(
+            &mut app,
+            &[
+                "--text",
+                "b",
+                "-p",
+                "src",
+                "-rx",
+                "*.o",
+                "--path=apps",
+                "-x*.a",
+                "--exclude",
+                "*.o",
+                "--dir",
+                "src",
+            ],
+        )
+        .unwrap();
+
+        assert_eq!(app.get_strings("path"), ["src", "apps", "src"]);
+        assert_eq!(app.get_indices("path"), [Some(4), Some(7), Some(12)]);
+        assert_eq!(app.get_strings("exclude"), ["*.o", "*.a"]);
+        assert_eq!(app.get_indices("exclude"), [Some(6), Some(8)]);
+        assert_eq!(app.get_indices("text"), [Some(2)]);
+        assert_eq!(app.get_indices("recurse"), [Some(5)]);
+        assert_eq!(app.get_indices("decompress"), [None]);
+        assert!(app.get_indices("limit").is_empty());
+
+        app.add_params_key_value("exclude", "*.a");
+        app.add_params_key_value("exclude", "*.so");
+        assert_eq!(app.get_strings("exclude"), ["*.o", "*.a", "*.so"]);
+        assert_eq!(app.get_indices("exclude"), [Some(6), Some(8), None]);
+    }
+
+    #[test]
+    fn test_config_layers() {
+        let dir = std::env::temp_dir().join(format!("cliparser-layers-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let (user, project) = (dir.join("config.toml"), dir.join(".project.toml"));
+        fs::write(&user, "recurse = true\nlimit = 5\npath = [\"a\", \"b\"]\n").unwrap();
+        fs::write(&project, "# project\nlimit = 7\ndir = [\"c\"]\n").unwrap();
+        // variables are handed in, tests running in parallel share the
+        // environment of the process
+        let vars = |name: &str, value: &str| [(OsString::from(name), OsString::from(value))];
+
+        let mut app = spec_parser();
+        app.add_user_config(&user).unwrap();
+        app.add_project_config(&project).unwrap();
+        app.add_vars("CLIPARSER_LAYERS", vars("CLIPARSER_LAYERS_LIMIT", "9"))
+            .unwrap();
+        parse(&mut app, &["--text", "name"]).unwrap();
+
+        assert!(app.get_bool("recurse"));
+        assert_eq!(app.get_integer("limit"), Some(9));
+        assert_eq!(app.get_strings("path"), ["c"]);
+        assert_eq!(app.get_indices("path"), [None]);
+        assert!(app.is_given("limit"));
+        assert!(!app.is_given("decompress"));
+        assert_eq!(
+            app.get_source("recurse"),
+            Some(&Source::UserConfig(user.clone()))
+        );
+        assert_eq!(
+            app.get_source("path"),
+            Some(&Source::ProjectConfig(project.clone()))
+        );
+        assert_eq!(
+            app.get_source("limit"),
+            Some(&Source::Environment("CLIPARSER_LAYERS_LIMIT".to_string()))
+        );
+        assert_eq!(app.get_source("text"), Some(&Source::CommandLine));
+        assert_eq!(app.get_source("decompress"), Some(&Source::Default));
+
+        let effective = app.effective_config();
+        println!("{}", effective);
+        let lines: Vec<&str> = effective.lines().collect();
+        assert_eq!(
+            lines[0],
+            format!(
+                "path = [\"c\"]       # project config {}",
+                project.display()
+            )
+        );
+        assert_eq!(
+            lines[1],
+            "recurse = true     # user config ".to_string() + &user.to_string_lossy()
+        );
+        assert_eq!(lines[4], "text = \"name\"      # command line");
+        assert_eq!(config::parse_toml(&effective).unwrap().len(), 5);
+
+        // the command line overrides everything
+        let mut app = spec_parser();
+        app.add_user_config(&user).unwrap();
+        app.add_vars("CLIPARSER_LAYERS", vars("CLIPARSER_LAYERS_LIMIT", "9"))
+            .unwrap();
+        parse(&mut app, &["--text", "name", "-l", "1", "--no-recurse"]).unwrap();
+        assert_eq!(app.get_integer("limit"), Some(1));
+        assert!(!app.get_bool("recurse"));
+        assert_eq!(app.get_strings("path"), ["a", "b"]);
+
+        fs::write(&project, "limit = 7\nrecurse = \"yes\"\n").unwrap();
+        let invalid = spec_parser().add_project_config(&project);
+        fs::write(&project, "recurce = true\n").unwrap();
+        let unknown = spec_parser().add_project_config(&project);
+        let env = spec_parser().add_vars(
+            "CLIPARSER_LAYERS_BAD",
+            vars("CLIPARSER_LAYERS_BAD_LIMIT", "x"),
+        );
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(
+            invalid,
+            Err(CliError::Config {
+                path: project.clone(),
+                line: 2,
+                message: "invalid value `yes` for `recurse`, expected either `true` or `false`"
+                    .to_string()
+            })
+        );
+        assert_eq!(
+            unknown.unwrap_err().to_string(),
+            format!(
+                "{}:1: unknown option `recurce`, did you mean `--recurse`?",
+                project.display()
+            )
+        );
+        assert_eq!(
+            env,
+            Err(CliError::InvalidValue {
+                option: "CLIPARSER_LAYERS_BAD_LIMIT".to_string(),
+                value: "x".to_string(),
+                expected: "a positive number".to_string()
+            })
+        );
+        assert!(spec_parser().add_user_config(&user).is_err());
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_bad_utf8() {
+        use std::ffi::OsString;
+        use std::os::unix::ffi::OsStringExt;
+
+        let mut app = spec_parser();
+        let s = vec![
+            OsString::from("cliparser"),
+            OsString::from("--text"),
+            OsString::from_vec(b"na\xffme".to_vec()),
+        ];
+        let error = app.set_params_os(&s).unwrap_err();
+        assert_eq!(
+            error,
+            CliError::BadUtf8 {
+                index: 2,
+                arg: "na\u{fffd}me".to_string()
+            }
+        );
+        assert_eq!(
+            error.to_string(),
+            "argument 2 `na\u{fffd}me` is not valid UTF-8"
+        );
+
+        let s = vec![OsString::from("cliparser"), OsString::from("--text=name")];
+        app.set_params_os(&s).unwrap();
+        app.parse_params().unwrap();
+        assert_eq!(app.get_string("text"), Some("name"));
+    }
 }
@@ -0,0 +1,389 @@
+use std::fmt;
+use std::iter::Peekable;
+use std::path::{Path, PathBuf};
+use std::str::Chars;
+
+/// Where the value of an option comes from, each one overrides the ones
+/// before it
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Source {
+    /// Default of the declared option
+    Default,
+    /// Config file of the user
+    UserConfig(PathBuf),
+    /// Config file of the project the program is run in
+    ProjectConfig(PathBuf),
+    /// Environment variable
+    Environment(String),
+    /// Command line argument
+    CommandLine,
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Source::Default => write!(f, "default"),
+            Source::UserConfig(path) => write!(f, "user config {}", path.display()),
+            Source::ProjectConfig(path) => write!(f, "project config {}", path.display()),
+            Source::Environment(var) => write!(f, "environment {}", var),
+            Source::CommandLine => write!(f, "command line"),
+        }
+    }
+}
+
+/// `config.toml` in the config directory of the program,
+/// `$XDG_CONFIG_HOME/<program>` or `~/.config/<program>`
+pub fn user_config_path(program: &str) -> Option<PathBuf> {
+    let config_dir = match std::env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(std::env::var_os("HOME")?).join(".config"),
+    };
+
+    Some(config_dir.join(program).join("config.toml"))
+}
+
+/// Closest file called `file_name` in `start` or one of its parents
+pub fn find_project_config(start: &Path, file_name: &str) -> Option<PathBuf> {
+    start
+        .ancestors()
+        .map(|dir| dir.join(file_name))
+        .find(|path| path.is_file())
+}
+
+/// `key = value` line of a config file, the values of an array are kept
+/// in order
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Entry {
+    pub(crate) key: String,
+    pub(crate) values: Vec<String>,
+    pub(crate) line: usize,
+}
+
+/// Values a config file can hold, named in the errors about the others
+const SUPPORTED_VALUES: &str = "values are strings, integers, booleans or arrays of them";
+
+/// Parse the part of TOML config files need, top-level `key = value`
+/// pairs with strings, integers, booleans and arrays of them. Tables,
+/// floats, dates and nested arrays are rejected. The error has the line it
+/// was found on.
+pub(crate) fn parse_toml(text: &str) -> Result<Vec<Entry>, (usize, String)> {
+    let mut parser = Parser {
+        chars: text.chars().peekable(),
+        line: 1,
+    };
+
+    let mut entries = Vec::<Entry>::new();
+    loop {
+        parser.skip_blank(true);
+        let c = match parser.chars.peek() {
+            Some(c) => *c,
+            None => return Ok(entries),
+        };
+
+        if c == '[' {
+            let header: String = parser.chars.clone().take_while(|c| *c != '\n').collect();
+            return Err(parser.error(&format!(
+                "tables like `{}` are not supported, options are top-level `key = value` lines",
+                header.trim_end()
+            )));
+        }
+
+        let line = parser.line;
+        let key = parser.key()?;
+        if entries.iter().any(|entry| entry.key == key) {
+            return Err((line, format!("duplicate key `{}`", key)));
+        }
+
+        parser.skip_blank(false);
+        if parser.chars.next() != Some('=') {
+            return Err(parser.error(&format!("expected `=` after `{}`", key)));
+        }
+        parser.skip_blank(false);
+
+        let values = if parser.chars.peek() == Some(&'[') {
+            parser.chars.next();
+            parser.array()?
+        } else {
+            vec![parser.value()?]
+        };
+
+        parser.skip_blank(false);
+        match parser.chars.next() {
+            None | Some('\n') => parser.line += 1,
+            Some(c) => return Err(parser.error(&format!("unexpected `{}` after the value", c))),
+        }
+
+        entries.push(Entry { key, values, line });
+    }
+}
+
+struct Parser<'a> {
+    chars: Peekable<Chars<'a>>,
+    line: usize,
+}
+
+impl Parser<'_> {
+    fn error(&self, message: &str) -> (usize, String) {
+        (self.line, message.to_string())
+    }
+
+    /// Skip spaces and comments, and line ends too if `newlines`
+    fn skip_blank(&mut self, newlines: bool) {
+        while let Some(c) = self.chars.peek() {
+            match c {
+                ' ' | '\t' | '\r' => {}
+                '\n' if newlines => self.line += 1,
+                '#' => {
+                    while self.chars.peek().is_some_and(|c| *c != '\n') {
+                        self.chars.next();
+                    }
+                    continue;
+                }
+                _ => return,
+            }
+            self.chars.next();
+        }
+    }
+
+    fn key(&mut self) -> Result<String, (usize, String)> {
+        match self.chars.peek() {
+            Some('"') | Some('\'') => return self.string(),
+            _ => {}
+        }
+
+        let mut key = String::new();
+        while let Some(c) = self.chars.peek() {
+            if !(c.is_ascii_alphanumeric() || *c == '_' || *c == '-') {
+                break;
+            }
+            key.push(*c);
+            self.chars.next();
+        }
+
+        if key.is_empty() {
+            return Err(self.error("expected a key"));
+        }
+        Ok(key)
+    }
+
+    /// Values of an array after its `[`, they can span lines
+    fn array(&mut self) -> Result<Vec<String>, (usize, String)> {
+        let mut values = Vec::<String>::new();
+        loop {
+            self.skip_blank(true);
+            if self.chars.peek() == Some(&']') {
+                self.chars.next();
+                return Ok(values);
+            }
+            if self.chars.peek() == Some(&'[') {
+                return Err(self.error(&format!(
+                    "nested arrays are not supported, {}",
+                    SUPPORTED_VALUES
+                )));
+            }
+
+            values.push(self.value()?);
+
+            self.skip_blank(true);
+            match self.chars.next() {
+                Some(',') => {}
+                Some(']') => return Ok(values),
+                _ => return Err(self.error("expected `,` or `]` in the array")),
+            }
+        }
+    }
+
+    /// String, integer or boolean
+    fn value(&mut self) -> Result<String, (usize, String)> {
+        match self.chars.peek() {
+            Some('"') | Some('\'') => return self.string(),
+            Some('{') => {
+                return Err(self.error(&format!(
+                    "inline tables are not supported, {}",
+                    SUPPORTED_VALUES
+                )))
+            }
+            None => return Err(self.error("expected a value")),
+            _ => {}
+        }
+
+        let mut word = String::new();
+        while let Some(c) = self.chars.peek() {
+            if !(c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '+' | '.' | ':')) {
+                break;
+            }
+            word.push(*c);
+            self.chars.next();
+        }
+
+        if word == "true" || word == "false" {
+            return Ok(word);
+        }
+
+        let digits = word.strip_prefix('+').unwrap_or(&word).replace('_', "");
+        if digits.parse::<i64>().is_ok() {
+            return Ok(digits);
+        }
+        if digits.parse::<f64>().is_ok() {
+            return Err(self.error(&format!(
+                "float `{}` is not supported, {}",
+                word, SUPPORTED_VALUES
+            )));
+        }
+
+        Err(self.error(&format!(
+            "invalid value `{}`, strings have to be quoted",
+            word
+        )))
+    }
+
+    /// Basic string with escapes in `"`, or literal string in `'`
+    fn string(&mut self) -> Result<String, (usize, String)> {
+        let quote = self.chars.next().unwrap();
+        let mut value = String::new();
+
+        loop {
+            let c = match self.chars.next() {
+                None | Some('\n') => return Err(self.error("unterminated string")),
+                Some(c) => c,
+            };
+
+            if c == quote {
+                return Ok(value);
+            }
+            if c != '\\' || quote == '\'' {
+                value.push(c);
+                continue;
+            }
+
+            let escaped = match self.chars.next() {
+                Some('n') => '\n',
+                Some('t') => '\t',
+                Some('r') => '\r',
+                Some('"') => '"',
+                Some('\\') => '\\',
+                Some('u') => {
+                    let hex: String = (0..4).filter_map(|_| self.chars.next()).collect();
+                    u32::from_str_radix(&hex, 16)
+                        .ok()
+                        .and_then(char::from_u32)
+                        .ok_or_else(|| self.error(&format!("invalid escape `\\u{}`", hex)))?
+                }
+                Some(c) => return Err(self.error(&format!("invalid escape `\\{}`", c))),
+                None => return Err(self.error("unterminated string")),
+            };
+            value.push(escaped);
+        }
+    }
+}
+
+/// Value written so `parse_toml` reads it back, numbers and booleans are
+/// left bare if the option takes them
+pub(crate) fn toml_value(value: &str, bare: bool) -> String {
+    if bare {
+        return value.to_string();
+    }
+
+    let mut quoted = String::from("\"");
+    for c in value.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\t' => quoted.push_str("\\t"),
+            '\r' => quoted.push_str("\\r"),
+            c if c.is_control() => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(key: &str, values: &[&str], line: usize) -> Entry {
+        Entry {
+            key: key.to_string(),
+            values: values.iter().map(|value| value.to_string()).collect(),
+            line,
+        }
+    }
+
+    #[test]
+    fn test_parse_toml() {
+        let text = "# textfinder\n\
+                    recurse = true\n\
+                    limit = 1_000 # at most\n\
+                    \n\
+                    text = \"say \\\"hi\\\"\\t\\u00e9\"\n\
+                    path = 'C:\\src'\n\
+                    exclude = [\n  \"*.o\", # objects\n  '*.a',\n]\n\
+                    \"files-from\" = \"list\"";
+        assert_eq!(
+            parse_toml(text),
+            Ok(vec![
+                entry("recurse", &["true"], 2),
+                entry("limit", &["1000"], 3),
+                entry("text", &["say \"hi\"\t\u{e9}"], 5),
+                entry("path", &["C:\\src"], 6),
+                entry("exclude", &["*.o", "*.a"], 7),
+                entry("files-from", &["list"], 11),
+            ])
+        );
+
+        assert_eq!(parse_toml(""), Ok(vec![]));
+        assert_eq!(parse_toml("a = \"x\nb = 1").unwrap_err().0, 1);
+        assert_eq!(parse_toml("a = 1\n\nb = src").unwrap_err().0, 3);
+        assert_eq!(parse_toml("a = 1\na = 2").unwrap_err().0, 2);
+        assert!(parse_toml("a = 1 2").is_err());
+        assert!(parse_toml("a = \"\\q\"").is_err());
+
+        // the parts of TOML config files do not need are rejected with what
+        // is accepted instead
+        let rejected = |text| parse_toml(text).unwrap_err().1;
+        assert_eq!(
+            rejected("a = 1\n[search]\nb = 2"),
+            "tables like `[search]` are not supported, options are top-level `key = value` lines"
+        );
+        assert_eq!(
+            rejected("a = 1.5"),
+            "float `1.5` is not supported, values are strings, integers, booleans or arrays of them"
+        );
+        assert_eq!(
+            rejected("a = [[1]]"),
+            "nested arrays are not supported, values are strings, integers, booleans or arrays of them"
+        );
+        assert_eq!(
+            rejected("a = { b = 1 }"),
+            "inline tables are not supported, values are strings, integers, booleans or arrays of them"
+        );
+        assert_eq!(
+            rejected("a = 1979-05-27T07:32:00"),
+            "invalid value `1979-05-27T07:32:00`, strings have to be quoted"
+        );
+
+        for value in ["plain", "say \"hi\"\t\\", "\u{1}"] {
+            let text = format!("a = {}", toml_value(value, false));
+            assert_eq!(parse_toml(&text), Ok(vec![entry("a", &[value], 1)]));
+        }
+    }
+
+    #[test]
+    fn test_find_project_config() {
+        let root = std::env::temp_dir().join(format!("cliparser-config-{}", std::process::id()));
+        let nested = root.join("a").join("b");
+        std::fs::create_dir_all(&nested).unwrap();
+        std::fs::write(root.join(".tool.toml"), "").unwrap();
+        std::fs::write(root.join("a").join(".tool.toml"), "").unwrap();
+
+        let found = find_project_config(&nested, ".tool.toml");
+        let missing = find_project_config(&nested, ".missing.toml");
+        std::fs::remove_dir_all(&root).unwrap();
+
+        assert_eq!(found, Some(root.join("a").join(".tool.toml")));
+        assert_eq!(missing, None);
+    }
+}
@@ -0,0 +1,97 @@
+use std::fmt;
+use std::path::PathBuf;
+
+/// Reasons the command line cannot be parsed, each names the argument or
+/// option at fault
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CliError {
+    /// An option that is not declared, with the closest declared one
+    UnknownOption {
+        arg: String,
+        suggestion: Option<String>,
+    },
+    /// An option given without the value it needs, or a required option
+    /// that is not given at all
+    MissingValue { option: String },
+    /// A value the option does not accept
+    InvalidValue {
+        option: String,
+        value: String,
+        expected: String,
+    },
+    /// A value that does not belong to any option
+    UnexpectedValue { arg: String },
+    /// An argument that is not valid UTF-8, shown lossily
+    BadUtf8 { index: usize, arg: String },
+    /// A config file that cannot be read or has a bad entry, the line is 0
+    /// when it is about the whole file
+    Config {
+        path: PathBuf,
+        line: usize,
+        message: String,
+    },
+}
+
+impl fmt::Display for CliError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CliError::UnknownOption { arg, suggestion } => {
+                write!(f, "unknown option `{}`", arg)?;
+                if let Some(suggestion) = suggestion {
+                    write!(f, ", did you mean `{}`?", suggestion)?;
+                }
+                Ok(())
+            }
+            CliError::MissingValue { option } => write!(f, "`{}` needs a value", option),
+            CliError::InvalidValue {
+                option,
+                value,
+                expected,
+            } => write!(
+                f,
+                "invalid value `{}` for `{}`, expected {}",
+                value, option, expected
+            ),
+            CliError::UnexpectedValue { arg } => {
+                write!(
+                    f,
+                    "unexpected value `{}`, it does not follow an option",
+                    arg
+                )
+            }
+            CliError::BadUtf8 { index, arg } => {
+                write!(f, "argument {} `{}` is not valid UTF-8", index, arg)
+            }
+            CliError::Config {
+                path,
+                line: 0,
+                message,
+            } => write!(f, "{}: {}", path.display(), message),
+            CliError::Config {
+                path,
+                line,
+                message,
+            } => write!(f, "{}:{}: {}", path.display(), line, message),
+        }
+    }
+}
+
+impl std::error::Error for CliError {}
+
+/// Number of single character edits turning `a` into `b`
+pub(crate) fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = diagonal + (ca != *cb) as usize;
+            diagonal = row[j + 1];
+            row[j + 1] = substitution.min(row[j] + 1).min(row[j + 1] + 1);
+        }
+    }
+
+    row[b.len()]
+}
@@ -0,0 +1,197 @@
+/// Kind of value an option takes, values are checked against it when the
+/// arguments are parsed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ValueType {
+    /// `true` or `false`
+    Bool,
+    /// A non-negative whole number
+    Integer,
+    /// Any text
+    Text,
+    /// A file or directory path
+    Path,
+}
+
+impl ValueType {
+    /// Check that `value` is valid for the type, the error says what is
+    /// expected instead
+    pub fn check(&self, value: &str) -> Result<(), String> {
+        match self {
+            ValueType::Bool if value != "true" && value != "false" => {
+                Err("either `true` or `false`".to_string())
+            }
+            ValueType::Integer if value.parse::<usize>().is_err() => {
+                Err("a positive number".to_string())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Placeholder for the value in the usage text
+    pub fn placeholder(&self) -> &'static str {
+        match self {
+            ValueType::Bool => "<true|false>",
+            ValueType::Integer => "<N>",
+            ValueType::Text => "<TEXT>",
+            ValueType::Path => "<PATH>",
+        }
+    }
+}
+
+/// Declaration of an option the parser accepts, built like
+///
+/// `OptionSpec::new("limit").value_type(ValueType::Integer).help("stop after this many hits")`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OptionSpec {
+    pub(crate) name: String,
+    pub(crate) aliases: Vec<String>,
+    pub(crate) short: Option<char>,
+    pub(crate) value_type: ValueType,
+    pub(crate) choices: Vec<String>,
+    pub(crate) default: Option<String>,
+    pub(crate) required: bool,
+    pub(crate) repeatable: bool,
+    pub(crate) positional: bool,
+    pub(crate) dedup: bool,
+    pub(crate) help: String,
+}
+
+impl OptionSpec {
+    /// Option `--name` taking a single text value
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_lowercase(),
+            aliases: Vec::new(),
+            short: None,
+            value_type: ValueType::Text,
+            choices: Vec::new(),
+            default: None,
+            required: false,
+            repeatable: false,
+            positional: false,
+            dedup: false,
+            help: String::new(),
+        }
+    }
+
+    /// Another name the option can be given by, e.g. `dir` for `path`
+    pub fn alias(mut self, alias: &str) -> Self {
+        self.aliases.push(alias.to_lowercase());
+        self
+    }
+
+    /// Single letter the option can be given by, `-r`, short boolean
+    /// options can be clustered like `-rn`
+    pub fn short(mut self, short: char) -> Self {
+        self.short = Some(short);
+        self
+    }
+
+    /// Type of the values, boolean options are switches that do not need
+    /// a value
+    pub fn value_type(mut self, value_type: ValueType) -> Self {
+        self.value_type = value_type;
+        self
+    }
+
+    /// The only values the option accepts, like `depth` and `breadth`
+    pub fn choices(mut self, choices: &[&str]) -> Self {
+        self.choices = choices.iter().map(|choice| choice.to_string()).collect();
+        self
+    }
+
+    /// Value used when the option is not given
+    pub fn default_value(mut self, default: &str) -> Self {
+        self.default = Some(default.to_string());
+        self
+    }
+
+    /// The option has to be given
+    pub fn required(mut self) -> Self {
+        self.required = true;
+        self
+    }
+
+    /// The option can be given more than once, all values are kept in the
+    /// order they are given
+    pub fn repeatable(mut self) -> Self {
+        self.repeatable = true;
+        self
+    }
+
+    /// Drop values given more than once, the first one keeps its place
+    pub fn dedup(mut self) -> Self {
+        self.dedup = true;
+        self
+    }
+
+    /// Values can also be given without the option, positional options
+    /// take the values in the order they are declared and the last one
+    /// takes the rest if it is repeatable
+    pub fn positional(mut self) -> Self {
+        self.positional = true;
+        self
+    }
+
+    /// Description of the option for the usage text
+    pub fn help(mut self, help: &str) -> Self {
+        self.help = help.to_string();
+        self
+    }
+
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn get_value_type(&self) -> ValueType {
+        self.value_type
+    }
+
+    pub fn get_choices(&self) -> &[String] {
+        &self.choices
+    }
+
+    pub fn get_default(&self) -> Option<&str> {
+        self.default.as_deref()
+    }
+
+    pub fn is_positional(&self) -> bool {
+        self.positional
+    }
+
+    pub fn get_help(&self) -> &str {
+        &self.help
+    }
+
+    /// Check that `value` is valid for the type and one of the choices if
+    /// there are any, the error says what is expected instead
+    pub fn check(&self, value: &str) -> Result<(), String> {
+        self.value_type.check(value)?;
+
+        if !self.choices.is_empty() && !self.choices.iter().any(|choice| choice == value) {
+            let choices: Vec<String> = self
+                .choices
+                .iter()
+                .map(|choice| format!("`{}`", choice))
+                .collect();
+            return Err(format!("one of {}", choices.join(", ")));
+        }
+
+        Ok(())
+    }
+
+    /// Placeholder for the value in the usage text, the choices if there
+    /// are any
+    pub fn placeholder(&self) -> String {
+        if self.choices.is_empty() {
+            self.value_type.placeholder().to_string()
+        } else {
+            format!("<{}>", self.choices.join("|"))
+        }
+    }
+
+    /// Check whether `--key` refers to this option
+    pub fn matches(&self, key: &str) -> bool {
+        self.name == key || self.aliases.iter().any(|alias| alias == key)
+    }
+}
@@ -18,9 +18,15 @@ impl DirEvent for Appl {
         }
     }
 
-    fn do_dir(&mut self, directory: &Path) {
+    fn do_dir(&mut self, directory: &Path) -> DirAction {
+        // Prune any directory that carries a skip marker
+        if directory.join(".textfinder-skip").exists() {
+            return DirAction::SkipChildren;
+        }
+
         self.current_directory = PathBuf::from(directory);
         self.display_directory = true;
+        DirAction::Descend
     }
 
     fn do_file(&mut self, file_name: &Path) {
@@ -0,0 +1,582 @@
+use crate::{EntryKind, EntryMetadata, FileSystem, MemoryFileSystem};
+use flate2::read::GzDecoder;
+use std::collections::VecDeque;
+use std::ffi::{OsStr, OsString};
+use std::io::{self, Cursor, Read};
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+/// Suffix appended to the name of an archive to turn it into a directory
+pub const ARCHIVE_SEPARATOR: &str = "!";
+
+/// Largest member read from an archive by default
+pub const MAX_MEMBER_SIZE: u64 = 64 * 1024 * 1024;
+
+/// Largest archive, and sum of its members, read by default
+pub const MAX_ARCHIVE_SIZE: u64 = 256 * 1024 * 1024;
+
+/// Archives whose members are kept in memory at a time
+const CACHED_ARCHIVES: usize = 4;
+
+/// Archive formats that can be walked like directories
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArchiveFormat {
+    Tar,
+    TarGz,
+    Zip,
+}
+
+impl ArchiveFormat {
+    /// Format of an archive judging by its file name
+    pub fn from_name(name: &OsStr) -> Option<Self> {
+        let name = name.to_str()?.to_ascii_lowercase();
+
+        if name.ends_with(".tar") {
+            Some(ArchiveFormat::Tar)
+        } else if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+            Some(ArchiveFormat::TarGz)
+        } else if name.ends_with(".zip") {
+            Some(ArchiveFormat::Zip)
+        } else {
+            None
+        }
+    }
+}
+
+/// `FileSystem` wrapper that shows each `.tar`, `.tar.gz`, `.tgz` and `.zip`
+/// file as a directory named after the archive followed by `!`, so members
+/// get paths like `bundle.tar.gz!/logs/app.log`. Archives inside archives are
+/// walked the same way.
+pub struct ArchiveFileSystem {
+    inner: Arc<dyn FileSystem>,
+
+    /// Members of the archives used last, the most recent first. A depth
+    /// first walk is done with an archive before it goes on to the next,
+    /// a breadth first walk interleaves the directories of the archives on
+    /// one level and reads an archive again each time it was dropped in
+    /// between, when more than `CACHED_ARCHIVES` archives are open at once.
+    cache: Mutex<VecDeque<(PathBuf, Arc<dyn FileSystem>)>>,
+
+    /// Archives with a larger member or more bytes in total are not
+    /// read, walks skip them and count them as too big
+    limits: Limits,
+}
+
+impl ArchiveFileSystem {
+    pub fn new(inner: Arc<dyn FileSystem>) -> Self {
+        Self {
+            inner,
+            cache: Mutex::new(VecDeque::new()),
+            limits: Limits {
+                member: MAX_MEMBER_SIZE,
+                total: MAX_ARCHIVE_SIZE,
+            },
+        }
+    }
+
+    /// Set the largest member and the largest archive, both unpacked,
+    /// that are read
+    pub fn with_limits(mut self, member: u64, total: u64) -> Self {
+        self.limits = Limits { member, total };
+        self
+    }
+
+    /// Split `a/bundle.tar.gz!/logs/app.log` into the archive path
+    /// `a/bundle.tar.gz` and the member path `logs/app.log`
+    fn split(path: &Path) -> Option<(PathBuf, PathBuf)> {
+        let mut archive = PathBuf::new();
+        let mut components = path.components();
+
+        for component in components.by_ref() {
+            if let Component::Normal(name) = component {
+                if let Some(stripped) = name
+                    .to_str()
+                    .and_then(|n| n.strip_suffix(ARCHIVE_SEPARATOR))
+                {
+                    if ArchiveFormat::from_name(OsStr::new(stripped)).is_some() {
+                        archive.push(stripped);
+                        return Some((archive, components.collect()));
+                    }
+                }
+            }
+            archive.push(component);
+        }
+
+        None
+    }
+
+    /// Members of an archive as a filesystem, read from the cache if the
+    /// archive was one of the last used
+    fn members(&self, archive: &Path) -> io::Result<Arc<dyn FileSystem>> {
+        let mut cache = self.cache.lock().unwrap();
+
+        if let Some(i) = cache.iter().position(|(path, _)| path == archive) {
+            let entry = cache.remove(i).unwrap();
+            let members = entry.1.clone();
+            cache.push_front(entry);
+            return Ok(members);
+        }
+
+        let members: Arc<dyn FileSystem> = Arc::new(ArchiveFileSystem {
+            inner: Arc::new(self.read_archive(archive)?),
+            cache: Mutex::new(VecDeque::new()),
+            limits: self.limits,
+        });
+        cache.push_front((archive.to_path_buf(), members.clone()));
+        cache.truncate(CACHED_ARCHIVES);
+
+        Ok(members)
+    }
+
+    /// Read all members of an archive into memory, members inherit the
+    /// times, owner and device of the archive unless the archive stores
+    /// their own. Archives over the limits are an error.
+    fn read_archive(&self, archive: &Path) -> io::Result<MemoryFileSystem> {
+        let format = archive
+            .file_name()
+            .and_then(ArchiveFormat::from_name)
+            .ok_or_else(|| io::Error::other("Not an archive"))?;
+
+        let base = self.inner.metadata(archive)?;
+        let reader = self.inner.open(archive)?;
+
+        if base.len > self.limits.total {
+            return Err(too_large(archive));
+        }
+
+        let mut limits = self.limits;
+        let mut members = MemoryFileSystem::new();
+        let read = match format {
+            ArchiveFormat::Tar => read_tar(reader, &base, &mut limits, &mut members),
+            ArchiveFormat::TarGz => {
+                read_tar(GzDecoder::new(reader), &base, &mut limits, &mut members)
+            }
+            ArchiveFormat::Zip => read_zip(reader, &base, &mut limits, &mut members),
+        };
+        match read {
+            Err(e) if e.kind() == io::ErrorKind::FileTooLarge => return Err(too_large(archive)),
+            read => read?,
+        }
+
+        let mut root = base;
+        root.kind = EntryKind::Dir;
+        members.set_metadata(Path::new(""), root)?;
+
+        Ok(members)
+    }
+
+    fn is_archive(&self, path: &Path) -> bool {
+        path.file_name()
+            .and_then(ArchiveFormat::from_name)
+            .is_some()
+            && self.inner.metadata(path).is_ok_and(|m| m.is_file())
+    }
+}
+
+impl FileSystem for ArchiveFileSystem {
+    fn read_dir(&self, dir: &Path) -> io::Result<Vec<OsString>> {
+        if let Some((archive, member)) = Self::split(dir) {
+            return self.members(&archive)?.read_dir(&member);
+        }
+
+        let names = self.inner.read_dir(dir)?;
+        Ok(names
+            .into_iter()
+            .map(|name| {
+                if self.is_archive(&dir.join(&name)) {
+                    let mut name = name;
+                    name.push(ARCHIVE_SEPARATOR);
+                    name
+                } else {
+                    name
+                }
+            })
+            .collect())
+    }
+
+    fn metadata(&self, path: &Path) -> io::Result<EntryMetadata> {
+        match Self::split(path) {
+            Some((archive, member)) if member.as_os_str().is_empty() => {
+                if !self.is_archive(&archive) {
+                    return Err(io::Error::new(
+                        io::ErrorKind::NotFound,
+                        format!("{} is not an archive", archive.to_string_lossy()),
+                    ));
+                }
+
+                let mut metadata = self.inner.metadata(&archive)?;
+                metadata.kind = EntryKind::Dir;
+                Ok(metadata)
+            }
+            Some((archive, member)) => self.members(&archive)?.metadata(&member),
+            None => self.inner.metadata(path),
+        }
+    }
+
+    fn open(&self, path: &Path) -> io::Result<Box<dyn Read + Send>> {
+        match Self::split(path) {
+            Some((archive, member)) => self.members(&archive)?.open(&member),
+            None => self.inner.open(path),
+        }
+    }
+}
+
+/// Bytes still allowed for a member and for the whole archive
+#[derive(Debug, Clone, Copy)]
+struct Limits {
+    member: u64,
+    total: u64,
+}
+
+impl Limits {
+    /// Contents of a member, fails with `FileTooLarge` once a limit is
+    /// passed whatever size the archive claims for the member
+    fn read(&mut self, reader: impl Read) -> io::Result<Vec<u8>> {
+        let limit = self.member.min(self.total);
+        let mut contents = Vec::new();
+        reader.take(limit + 1).read_to_end(&mut contents)?;
+
+        if contents.len() as u64 > limit {
+            return Err(io::ErrorKind::FileTooLarge.into());
+        }
+        self.total -= contents.len() as u64;
+        Ok(contents)
+    }
+
+    /// Fail early on the size a member claims
+    fn check(&self, size: u64) -> io::Result<()> {
+        if size > self.member.min(self.total) {
+            return Err(io::ErrorKind::FileTooLarge.into());
+        }
+        Ok(())
+    }
+}
+
+fn too_large(archive: &Path) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::FileTooLarge,
+        format!("{} is too large to unpack", archive.to_string_lossy()),
+    )
+}
+
+/// Member paths that stay inside the archive, `..` and absolute paths are
+/// dropped
+fn member_path(path: &Path) -> Option<PathBuf> {
+    let path: PathBuf = path
+        .components()
+        .filter(|c| !matches!(c, Component::CurDir))
+        .map(|c| match c {
+            Component::Normal(name) => Some(name),
+            _ => None,
+        })
+        .collect::<Option<PathBuf>>()?;
+
+    if path.as_os_str().is_empty() {
+        None
+    } else {
+        Some(path)
+    }
+}
+
+fn read_tar(
+    reader: impl Read,
+    base: &EntryMetadata,
+    limits: &mut Limits,
+    members: &mut MemoryFileSystem,
+) -> io::Result<()> {
+    let mut archive = tar::Archive::new(reader);
+
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let path = match member_path(&entry.path()?) {
+            Some(path) => path,
+            None => continue,
+        };
+
+        let header = entry.header();
+        let kind = if header.entry_type().is_dir() {
+            EntryKind::Dir
+        } else if header.entry_type().is_file() {
+            EntryKind::File
+        } else {
+            // links and special files have no contents to search
+            continue;
+        };
+
+        let mut metadata = base.clone();
+        metadata.inode = None;
+        metadata.kind = kind;
+        metadata.len = header.size()?;
+        if let Ok(mtime) = header.mtime() {
+            metadata.modified = SystemTime::UNIX_EPOCH.checked_add(Duration::from_secs(mtime));
+            metadata.changed = metadata.modified;
+        }
+        metadata.mode = header.mode().ok().or(metadata.mode);
+        metadata.uid = header.uid().ok().map(|u| u as u32).or(metadata.uid);
+        metadata.gid = header.gid().ok().map(|g| g as u32).or(metadata.gid);
+
+        if kind == EntryKind::Dir {
+            members.add_dir(&path);
+        } else {
+            limits.check(metadata.len)?;
+            members.add_file(&path, &limits.read(&mut entry)?);
+        }
+        members.set_metadata(&path, metadata)?;
+    }
+
+    Ok(())
+}
+
+fn read_zip(
+    reader: impl Read,
+    base: &EntryMetadata,
+    limits: &mut Limits,
+    members: &mut MemoryFileSystem,
+) -> io::Result<()> {
+    // zip keeps its index at the end, so it needs the whole archive to seek
+    // in, it is held to the total limit as well
+    let buffer = Limits {
+        member: limits.total,
+        total: limits.total,
+    }
+    .read(reader)?;
+    let mut archive = zip::ZipArchive::new(Cursor::new(buffer))?;
+
+    for index in 0..archive.len() {
+        let mut file = archive.by_index(index)?;
+        let path = match file.enclosed_name().as_deref().and_then(member_path) {
+            Some(path) => path,
+            None => continue,
+        };
+
+        let mut metadata = base.clone();
+        metadata.inode = None;
+        metadata.mode = file.unix_mode().or(metadata.mode);
+
+        if file.is_dir() {
+            metadata.kind = EntryKind::Dir;
+            metadata.len = 0;
+            members.add_dir(&path);
+        } else {
+            limits.check(file.size())?;
+            let contents = limits.read(&mut file)?;
+            metadata.kind = EntryKind::File;
+            metadata.len = contents.len() as u64;
+            members.add_file(&path, &contents);
+        }
+        members.set_metadata(&path, metadata)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    fn tar_bytes(files: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut builder = tar::Builder::new(Vec::new());
+        for (path, contents) in files {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(contents.len() as u64);
+            header.set_mode(0o644);
+            header.set_mtime(1_000_000);
+            header.set_cksum();
+            builder.append_data(&mut header, path, *contents).unwrap();
+        }
+        builder.into_inner().unwrap()
+    }
+
+    fn zip_bytes(files: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut writer = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        for (path, contents) in files {
+            writer
+                .start_file(*path, zip::write::SimpleFileOptions::default())
+                .unwrap();
+            writer.write_all(contents).unwrap();
+        }
+        writer.finish().unwrap().into_inner()
+    }
+
+    #[test]
+    fn test_split() {
+        assert_eq!(
+            ArchiveFileSystem::split(Path::new("./a/bundle.tar.gz!/logs/app.log")),
+            Some((
+                PathBuf::from("./a/bundle.tar.gz"),
+                PathBuf::from("logs/app.log")
+            ))
+        );
+        assert_eq!(
+            ArchiveFileSystem::split(Path::new("a/b.zip!")),
+            Some((PathBuf::from("a/b.zip"), PathBuf::new()))
+        );
+        assert_eq!(ArchiveFileSystem::split(Path::new("a/wow!/b")), None);
+        assert_eq!(ArchiveFileSystem::split(Path::new("a/b.zip")), None);
+
+        assert_eq!(
+            member_path(Path::new("./logs/app.log")),
+            Some(PathBuf::from("logs/app.log"))
+        );
+        assert_eq!(member_path(Path::new("../escape.log")), None);
+        assert_eq!(member_path(Path::new("/etc/passwd")), None);
+    }
+
+    #[test]
+    fn test_archive_file_system() {
+        let mut gz = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        gz.write_all(&tar_bytes(&[("logs/app.log", b"ERROR disk full\n")]))
+            .unwrap();
+
+        let mut inner = MemoryFileSystem::new();
+        inner.add_file(Path::new("d/bundle.tar.gz"), &gz.finish().unwrap());
+        inner.add_file(
+            Path::new("d/release.zip"),
+            &zip_bytes(&[("bin/readme.txt", b"hello")]),
+        );
+        inner.add_file(Path::new("d/plain.txt"), b"plain");
+        inner.add_dir(Path::new("d/dir.zip"));
+
+        let fs = ArchiveFileSystem::new(Arc::new(inner));
+
+        let mut names = fs.read_dir(Path::new("d")).unwrap();
+        names.sort();
+        assert_eq!(
+            names,
+            vec![
+                OsString::from("bundle.tar.gz!"),
+                OsString::from("dir.zip"),
+                OsString::from("plain.txt"),
+                OsString::from("release.zip!")
+            ]
+        );
+
+        assert!(fs.metadata(Path::new("d/bundle.tar.gz!")).unwrap().is_dir());
+        assert!(fs.metadata(Path::new("d/dir.zip!")).is_err());
+        assert_eq!(
+            fs.read_dir(Path::new("d/bundle.tar.gz!")).unwrap(),
+            vec![OsString::from("logs")]
+        );
+
+        let log = fs
+            .metadata(Path::new("d/bundle.tar.gz!/logs/app.log"))
+            .unwrap();
+        assert_eq!(log.len, 16);
+        assert_eq!(log.mode, Some(0o644));
+        assert_eq!(
+            log.modified,
+            SystemTime::UNIX_EPOCH.checked_add(Duration::from_secs(1_000_000))
+        );
+
+        let mut contents = String::new();
+        fs.open(Path::new("d/bundle.tar.gz!/logs/app.log"))
+            .unwrap()
+            .read_to_string(&mut contents)
+            .unwrap();
+        assert_eq!(contents, "ERROR disk full\n");
+
+        contents.clear();
+        fs.open(Path::new("d/release.zip!/bin/readme.txt"))
+            .unwrap()
+            .read_to_string(&mut contents)
+            .unwrap();
+        assert_eq!(contents, "hello");
+    }
+
+    /// Filesystem counting the files opened
+    struct CountOpens {
+        fs: MemoryFileSystem,
+        opens: Mutex<usize>,
+    }
+
+    impl FileSystem for CountOpens {
+        fn read_dir(&self, dir: &Path) -> io::Result<Vec<OsString>> {
+            self.fs.read_dir(dir)
+        }
+
+        fn metadata(&self, path: &Path) -> io::Result<EntryMetadata> {
+            self.fs.metadata(path)
+        }
+
+        fn open(&self, path: &Path) -> io::Result<Box<dyn Read + Send>> {
+            *self.opens.lock().unwrap() += 1;
+            self.fs.open(path)
+        }
+    }
+
+    #[test]
+    fn test_archive_cache() {
+        let mut fs = MemoryFileSystem::new();
+        for i in 0..=CACHED_ARCHIVES {
+            fs.add_file(
+                Path::new(&format!("d/{}.tar", i)),
+                &tar_bytes(&[("logs/app.log", b"ERROR")]),
+            );
+        }
+        let inner = Arc::new(CountOpens {
+            fs,
+            opens: Mutex::new(0),
+        });
+        let fs = ArchiveFileSystem::new(inner.clone());
+
+        // directories of several archives in turn, as a breadth first walk
+        // visits them, only read each archive once
+        for dir in ["", "logs"] {
+            for i in 0..CACHED_ARCHIVES {
+                let path = format!("d/{}.tar!/{}", i, dir);
+                assert_eq!(fs.read_dir(Path::new(&path)).unwrap().len(), 1);
+            }
+        }
+        assert_eq!(*inner.opens.lock().unwrap(), CACHED_ARCHIVES);
+
+        // one more drops the archive used longest ago
+        let last = format!("d/{}.tar!", CACHED_ARCHIVES);
+        fs.read_dir(Path::new(&last)).unwrap();
+        fs.read_dir(Path::new("d/0.tar!")).unwrap();
+        assert_eq!(*inner.opens.lock().unwrap(), CACHED_ARCHIVES + 2);
+    }
+
+    #[test]
+    fn test_archive_limits() {
+        let mut inner = MemoryFileSystem::new();
+        inner.add_file(
+            Path::new("d/small.tar"),
+            &tar_bytes(&[("a.txt", b"0123"), ("b.txt", b"0123")]),
+        );
+        inner.add_file(
+            Path::new("d/member.tar"),
+            &tar_bytes(&[("a.txt", b"0123456789")]),
+        );
+        inner.add_file(
+            Path::new("d/release.zip"),
+            &zip_bytes(&[("a.txt", b"01234"), ("b.txt", b"01234")]),
+        );
+        let inner: Arc<dyn FileSystem> = Arc::new(inner);
+
+        let fs = ArchiveFileSystem::new(inner.clone()).with_limits(8, 4096);
+        assert_eq!(fs.read_dir(Path::new("d/small.tar!")).unwrap().len(), 2);
+        assert_eq!(
+            fs.read_dir(Path::new("d/member.tar!")).unwrap_err().kind(),
+            io::ErrorKind::FileTooLarge
+        );
+        assert_eq!(fs.read_dir(Path::new("d/release.zip!")).unwrap().len(), 2);
+
+        // the archive itself fits, its members together do not
+        let zeros = [0u8; 4096];
+        let mut gz = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        gz.write_all(&tar_bytes(&[("a.bin", &zeros), ("b.bin", &zeros)]))
+            .unwrap();
+        let mut inner = MemoryFileSystem::new();
+        inner.add_file(Path::new("d/zeros.tar.gz"), &gz.finish().unwrap());
+
+        let fs = ArchiveFileSystem::new(Arc::new(inner)).with_limits(4096, 6000);
+        assert_eq!(
+            fs.read_dir(Path::new("d/zeros.tar.gz!"))
+                .unwrap_err()
+                .kind(),
+            io::ErrorKind::FileTooLarge
+        );
+    }
+}
@@ -1,16 +1,130 @@
-use std::fs::{self, DirEntry};
+use std::cmp::Ordering;
+use std::collections::VecDeque;
+use std::ffi::{OsStr, OsString};
 use std::io;
-use std::io::{Error, ErrorKind};
+use std::io::Error;
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Instant;
+
+mod archive;
+mod filter;
+mod stats;
+mod types;
+mod vfs;
+pub use archive::{
+    ArchiveFileSystem, ArchiveFormat, ARCHIVE_SEPARATOR, MAX_ARCHIVE_SIZE, MAX_MEMBER_SIZE,
+};
+pub use filter::{parse_duration, FileFilter};
+pub use stats::TraversalStats;
+pub use types::{glob_match, shebang_interpreter, FileType, TypeRegistry};
+pub use vfs::{EntryKind, EntryMetadata, FileSystem, MemoryFileSystem, StdFileSystem};
 
 /// typedef of Vector of PathBuf to hold file extensions
 type SearchPatterns = Vec<PathBuf>;
 
+/// Order in which files and subdirectories of a directory are visited
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortOrder {
+    /// Order returned by the filesystem, fastest but not deterministic
+    None,
+    /// Natural (version) sort on entry name, `file2` before `file10`
+    Name,
+    /// Ascending size in bytes, ties broken by name
+    Size,
+    /// Ascending modification time, ties broken by name
+    Modified,
+}
+
+impl std::str::FromStr for SortOrder {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(SortOrder::None),
+            "name" => Ok(SortOrder::Name),
+            "size" => Ok(SortOrder::Size),
+            "mtime" => Ok(SortOrder::Modified),
+            _ => Err(format!(
+                "invalid sort order `{}`, valid values are `none`, `name`, `size` or `mtime`",
+                s
+            )),
+        }
+    }
+}
+
+/// Default maximum number of directories waiting in the breadth first queue
+pub const DEFAULT_QUEUE_LIMIT: usize = 65536;
+
+/// Strategy used to walk the directory tree
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TraversalMode {
+    /// Descend into each subdirectory before visiting its siblings
+    DepthFirst,
+    /// Visit all directories at one depth before going deeper
+    BreadthFirst,
+}
+
+impl std::str::FromStr for TraversalMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "depth" => Ok(TraversalMode::DepthFirst),
+            "breadth" => Ok(TraversalMode::BreadthFirst),
+            _ => Err(format!(
+                "invalid traversal `{}`, valid values are `depth` or `breadth`",
+                s
+            )),
+        }
+    }
+}
+
+/// Kind of a directory entry that is neither a regular file nor a directory
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpecialFile {
+    /// Named pipe, reading blocks until a writer shows up
+    Fifo,
+    /// Unix domain socket
+    Socket,
+    /// Block device such as a disk
+    BlockDevice,
+    /// Character device such as `/dev/zero`, may never end
+    CharDevice,
+}
+
+/// Decision returned by `DirEvent::do_dir` to steer the walk
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DirAction {
+    /// Visit files of the directory and descend into its subdirectories
+    Descend,
+    /// Do not look inside the directory, continue with its siblings
+    SkipChildren,
+    /// End the walk, no further directories or files are visited
+    Stop,
+}
+
 /// Trait Event to process subdirectories and files in a directory
 pub trait DirEvent {
     fn new() -> Self;
-    fn do_dir(&mut self, d: &Path);
+    fn do_dir(&mut self, d: &Path) -> DirAction;
     fn do_file(&mut self, f: &Path);
+
+    /// Called for FIFOs, sockets and device files which are skipped unless
+    /// special files are included
+    fn do_special(&mut self, _f: &Path, _kind: SpecialFile) {}
+
+    /// Return true once no more files are needed, stops the walk early
+    fn done(&self) -> bool {
+        false
+    }
+
+    /// Add the app's own counters such as bytes read to the statistics
+    fn add_stats(&self, _stats: &mut TraversalStats) {}
+
+    /// Called when `DirNav` is switched to another filesystem, apps reading
+    /// file contents should read them through it
+    fn set_file_system(&mut self, _fs: Arc<dyn FileSystem>) {}
 }
 
 pub struct DirNav<App: DirEvent> {
@@ -20,14 +134,53 @@ pub struct DirNav<App: DirEvent> {
     /// Instance of implementation of Trait DirEvent
     app: App,
 
-    /// Number of files processed
-    num_file: usize,
+    /// Filesystem the tree is read from
+    fs: Arc<dyn FileSystem>,
 
-    /// Number of directories processed
-    num_dirs: usize,
+    /// Counters of the walks done so far
+    stats: TraversalStats,
 
     /// Boolean flag for recursive search
     recurse: bool,
+
+    /// Order in which directory entries are visited
+    sort_order: SortOrder,
+
+    /// Depth first or breadth first walk
+    mode: TraversalMode,
+
+    /// Maximum number of directories queued in breadth first mode
+    queue_limit: usize,
+
+    /// Set once `do_dir` asks to stop the walk
+    stopped: bool,
+
+    /// Predicates every file has to pass before `do_file` is called
+    filters: Vec<FileFilter>,
+
+    /// Boolean flag to stay on the filesystem of the root directory
+    one_file_system: bool,
+
+    /// Device ID of the root directory of the current walk
+    root_device: Option<u64>,
+
+    /// Directories not descended into as they are on another filesystem
+    skipped_mounts: Vec<PathBuf>,
+
+    /// Boolean flag to hand special files to `do_file`
+    include_special: bool,
+
+    /// Known file types, built in and user defined
+    types: TypeRegistry,
+
+    /// Types to search in addition to the patterns
+    selected_types: Vec<String>,
+
+    /// Types never to search
+    excluded_types: Vec<String>,
+
+    /// Absolute paths of files never handed to `do_file`
+    ignored_files: Vec<PathBuf>,
 }
 
 impl<App: DirEvent + Default> DirNav<App> {
@@ -38,9 +191,22 @@ impl<App: DirEvent + Default> DirNav<App> {
         Self {
             patterns: SearchPatterns::new(),
             app: App::new(),
-            num_file: 0,
-            num_dirs: 0,
+            fs: Arc::new(StdFileSystem),
+            stats: TraversalStats::default(),
             recurse: false,
+            sort_order: SortOrder::None,
+            mode: TraversalMode::DepthFirst,
+            queue_limit: DEFAULT_QUEUE_LIMIT,
+            stopped: false,
+            filters: Vec::new(),
+            one_file_system: false,
+            root_device: None,
+            skipped_mounts: Vec::new(),
+            include_special: false,
+            types: TypeRegistry::new(),
+            selected_types: Vec::new(),
+            excluded_types: Vec::new(),
+            ignored_files: Vec::new(),
         }
     }
 
@@ -49,6 +215,59 @@ impl<App: DirEvent + Default> DirNav<App> {
         self.patterns.push(pattern.to_path_buf());
     }
 
+    /// Search files of a named type such as `rust`, see `TypeRegistry`
+    pub fn add_type(&mut self, name: &str) -> Result<(), String> {
+        self.types.get(name)?;
+        self.selected_types.push(name.to_string());
+        Ok(())
+    }
+
+    /// Never search files of a named type such as `markdown`
+    pub fn add_type_not(&mut self, name: &str) -> Result<(), String> {
+        self.types.get(name)?;
+        self.excluded_types.push(name.to_string());
+        Ok(())
+    }
+
+    /// Get the type registry to add user defined types or list them
+    pub fn get_type_registry(&mut self) -> &mut TypeRegistry {
+        &mut self.types
+    }
+
+    /// Add a size, time, owner or permission filter, files have to pass
+    /// all filters to be processed
+    pub fn add_filter(&mut self, filter: FileFilter) {
+        self.filters.push(filter);
+    }
+
+    /// Never hand `path` to do_file, e.g. a file the app writes into the
+    /// tree it walks. Paths are compared without resolving symlinks.
+    pub fn add_ignored_file(&mut self, path: &Path) {
+        if let Ok(path) = std::path::absolute(path) {
+            self.ignored_files.push(path);
+        }
+    }
+
+    /// Walk a different filesystem such as a `MemoryFileSystem`, the app is
+    /// switched to it as well
+    pub fn set_file_system(&mut self, fs: Arc<dyn FileSystem>) {
+        self.app.set_file_system(fs.clone());
+        self.fs = fs;
+    }
+
+    /// Descend into `.tar`, `.tar.gz`, `.tgz` and `.zip` files as if they
+    /// were directories named `<archive>!`, wraps the current filesystem.
+    /// Archives over `MAX_ARCHIVE_SIZE` or with a member over
+    /// `MAX_MEMBER_SIZE` are skipped and counted as too big.
+    pub fn set_search_archives(&mut self) {
+        self.set_file_system(Arc::new(ArchiveFileSystem::new(self.fs.clone())));
+    }
+
+    /// Get the filesystem the tree is read from
+    pub fn get_file_system(&self) -> Arc<dyn FileSystem> {
+        self.fs.clone()
+    }
+
     /// Get instance to App of trait DirEvent
     pub fn get_app(&mut self) -> &mut App {
         &mut self.app
@@ -56,12 +275,30 @@ impl<App: DirEvent + Default> DirNav<App> {
 
     /// Returns processed file count
     pub fn get_number_of_files_processed(&self) -> usize {
-        self.num_file
+        self.stats.files_seen
     }
 
     /// Returns processed dirs count
     pub fn get_number_of_dirs_processed(&self) -> usize {
-        self.num_dirs
+        self.stats.dirs_visited
+    }
+
+    /// Returns count of FIFOs, sockets and device files found
+    pub fn get_number_of_special_files(&self) -> usize {
+        self.stats.files_special
+    }
+
+    /// Returns statistics of all walks so far, including the app's counters
+    pub fn get_stats(&self) -> TraversalStats {
+        let mut stats = self.stats.clone();
+        self.app.add_stats(&mut stats);
+        stats
+    }
+
+    /// Hand FIFOs, sockets and device files to `do_file` like regular
+    /// files, reading them may block or never end
+    pub fn set_include_special_files(&mut self) {
+        self.include_special = true;
     }
 
     /// Function to set visits as recursive
@@ -69,57 +306,438 @@ impl<App: DirEvent + Default> DirNav<App> {
         self.recurse = true;
     }
 
+    /// Do not descend into directories on a different filesystem (device)
+    /// than the directory the walk started from
+    pub fn set_one_file_system(&mut self) {
+        self.one_file_system = true;
+    }
+
+    /// Returns mount points skipped because of `set_one_file_system`
+    pub fn get_skipped_mount_points(&self) -> &Vec<PathBuf> {
+        &self.skipped_mounts
+    }
+
+    /// Set the order in which files and subdirectories are visited
+    pub fn set_sort_order(&mut self, order: SortOrder) {
+        self.sort_order = order;
+    }
+
+    /// Set whether the tree is walked depth first or breadth first
+    pub fn set_traversal_mode(&mut self, mode: TraversalMode) {
+        self.mode = mode;
+    }
+
+    /// Bound the breadth first queue, directories found while the queue is
+    /// full are walked depth first right away to keep memory bounded
+    pub fn set_queue_limit(&mut self, limit: usize) {
+        self.queue_limit = limit.max(1);
+    }
+
     /// Reset to default state
     pub fn reset(&mut self) {
         self.patterns.clear();
         self.app = App::default();
-        self.num_file = 0;
-        self.num_dirs = 0;
+        self.fs = Arc::new(StdFileSystem);
+        self.stats = TraversalStats::default();
         self.recurse = false;
+        self.sort_order = SortOrder::None;
+        self.mode = TraversalMode::DepthFirst;
+        self.queue_limit = DEFAULT_QUEUE_LIMIT;
+        self.stopped = false;
+        self.filters.clear();
+        self.one_file_system = false;
+        self.root_device = None;
+        self.skipped_mounts.clear();
+        self.include_special = false;
+        self.types = TypeRegistry::new();
+        self.selected_types.clear();
+        self.excluded_types.clear();
+        self.ignored_files.clear();
     }
 
-    /// DFS on given path, call do_dir and do_file
+    /// Walk given path with the configured traversal mode, call do_dir and
+    /// do_file. Only an error reading `dir` itself is returned, a
+    /// subdirectory that cannot be read is skipped and counted in
+    /// `TraversalStats::errors`.
     pub fn visit(&mut self, dir: &Path) -> io::Result<()>
     where
         App: DirEvent,
     {
-        self.app.do_dir(dir);
-        self.num_dirs += 1;
-
-        let mut sub_dirs = Vec::<PathBuf>::new();
-
-        // if it is a directory
-        if dir.is_dir() {
-            // read the contents of directory
-            for entry in fs::read_dir(dir)? {
-                let entry = entry?;
-                let path = entry.path();
-
-                if path.is_dir() {
-                    sub_dirs.push(path);
-                } else {
-                    self.num_file += 1;
-                    if self.has_pattern(&entry) | self.patterns.is_empty() {
-                        self.app.do_file(Path::new(&entry.file_name()));
+        self.stopped = false;
+        self.root_device = if self.one_file_system {
+            self.fs.metadata(dir).ok().and_then(|m| m.device)
+        } else {
+            None
+        };
+
+        let start = Instant::now();
+
+        let result = match self.mode {
+            TraversalMode::DepthFirst => self.visit_depth_first(dir),
+            TraversalMode::BreadthFirst => self.visit_breadth_first(dir),
+        };
+
+        self.stats.elapsed += start.elapsed();
+        result
+    }
+
+    /// Call do_file for a list of files, e.g. from `git ls-files`, instead
+    /// of walking a tree. Files still go through the patterns, types and
+    /// filters, do_dir is called whenever the parent directory changes and
+    /// files that cannot be read or are directories are counted as errors.
+    pub fn visit_files<I>(&mut self, files: I)
+    where
+        I: IntoIterator<Item = PathBuf>,
+    {
+        self.stopped = false;
+        let start = Instant::now();
+
+        let mut current_dir: Option<PathBuf> = None;
+        let mut skip_dir = false;
+
+        for path in files {
+            if self.should_stop() {
+                break;
+            }
+
+            let (dir, name) = match (path.parent(), path.file_name()) {
+                (Some(dir), Some(name)) if !dir.as_os_str().is_empty() => (dir, name),
+                (_, Some(name)) => (Path::new("."), name),
+                _ => {
+                    self.stats.errors += 1;
+                    continue;
+                }
+            };
+
+            if current_dir.as_deref() != Some(dir) {
+                current_dir = Some(dir.to_path_buf());
+                self.stats.dirs_visited += 1;
+                match self.app.do_dir(dir) {
+                    DirAction::Descend => skip_dir = false,
+                    DirAction::SkipChildren => {
+                        self.stats.dirs_pruned += 1;
+                        skip_dir = true;
                     }
+                    DirAction::Stop => {
+                        self.stopped = true;
+                        break;
+                    }
+                }
+            }
+
+            if skip_dir {
+                continue;
+            }
+
+            let metadata = match self.fs.metadata(&path) {
+                Ok(metadata) if !metadata.is_dir() => metadata,
+                Ok(_) => {
+                    self.stats.errors += 1;
+                    continue;
+                }
+                Err(e) => {
+                    self.count_error(&e);
+                    continue;
+                }
+            };
+
+            let entry = Entry {
+                name: name.to_os_string(),
+                path: path.clone(),
+                metadata: Some(metadata),
+            };
+
+            match entry.metadata.as_ref().map(|m| m.kind) {
+                Some(EntryKind::Special(kind)) if !self.include_special => {
+                    self.stats.files_special += 1;
+                    self.app.do_special(Path::new(&entry.name), kind);
+                }
+                _ => self.visit_file(&entry),
+            }
+        }
+
+        self.stats.elapsed += start.elapsed();
+    }
+
+    /// DFS on given path
+    fn visit_depth_first(&mut self, dir: &Path) -> io::Result<()> {
+        let sub_dirs = self.visit_dir(dir)?;
+
+        // recurse into directory
+        if self.recurse {
+            for dir in sub_dirs {
+                if self.should_stop() {
+                    break;
+                }
+                // an unreadable subdirectory does not end the walk
+                if let Err(e) = self.visit_depth_first(&dir) {
+                    self.count_error(&e);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// BFS on given path, shallow directories are reported first
+    fn visit_breadth_first(&mut self, dir: &Path) -> io::Result<()> {
+        let mut queue = VecDeque::<PathBuf>::new();
+        queue.push_back(dir.to_path_buf());
+
+        let root = dir;
+
+        while let Some(dir) = queue.pop_front() {
+            if self.should_stop() {
+                break;
+            }
+
+            let sub_dirs = match self.visit_dir(&dir) {
+                Ok(sub_dirs) => sub_dirs,
+                Err(e) if dir == root => return Err(e),
+                Err(e) => {
+                    // an unreadable subdirectory does not end the walk
+                    self.count_error(&e);
+                    continue;
+                }
+            };
+
+            if !self.recurse {
+                continue;
+            }
+
+            for dir in sub_dirs {
+                // an overflow directory may have stopped the walk
+                if self.should_stop() {
+                    break;
+                }
+                if queue.len() < self.queue_limit {
+                    queue.push_back(dir);
+                } else if let Err(e) = self.visit_depth_first(&dir) {
+                    self.count_error(&e);
                 }
             }
+        }
+
+        Ok(())
+    }
+
+    /// Count a directory or file that could not be read, archives over a
+    /// size limit are counted as too big rather than as errors
+    fn count_error(&mut self, e: &io::Error) {
+        if e.kind() == io::ErrorKind::FileTooLarge {
+            self.stats.files_too_big += 1;
+        } else {
+            self.stats.errors += 1;
+        }
+    }
+
+    /// Call do_dir and do_file for a single directory, returns its
+    /// subdirectories in visiting order
+    fn visit_dir(&mut self, dir: &Path) -> io::Result<Vec<PathBuf>> {
+        let dir_metadata = self.fs.metadata(dir);
 
-            // recurse into directory
-            if self.recurse {
-                for dir in sub_dirs {
-                    self.visit(&dir)?;
+        // a directory whose metadata cannot be read is not a mount point,
+        // it fails below and is counted as an error
+        if let (Some(root_device), Ok(metadata)) = (self.root_device, &dir_metadata) {
+            if metadata.device != Some(root_device) {
+                if !self.skipped_mounts.iter().any(|m| m == dir) {
+                    self.skipped_mounts.push(dir.to_path_buf());
                 }
+                self.stats.dirs_pruned += 1;
+                return Ok(Vec::new());
+            }
+        }
+
+        let action = self.app.do_dir(dir);
+        self.stats.dirs_visited += 1;
+
+        match action {
+            DirAction::Descend => {}
+            DirAction::SkipChildren => {
+                self.stats.dirs_pruned += 1;
+                return Ok(Vec::new());
+            }
+            DirAction::Stop => {
+                self.stopped = true;
+                return Ok(Vec::new());
+            }
+        }
+
+        // if it is not a directory
+        if !dir_metadata.is_ok_and(|m| m.is_dir()) {
+            return Err(Error::other("Not a directory"));
+        }
+
+        let mut sub_dirs = Vec::<Entry>::new();
+        let mut files = Vec::<Entry>::new();
+        let mut specials = Vec::<Entry>::new();
+
+        // read the contents of directory
+        for name in self.fs.read_dir(dir)? {
+            let path = dir.join(&name);
+            let metadata = self.fs.metadata(&path).ok();
+            let kind = metadata.as_ref().map(|m| m.kind);
+            let entry = Entry {
+                name,
+                path,
+                metadata,
+            };
+
+            match kind {
+                Some(EntryKind::Dir) => sub_dirs.push(entry),
+                Some(EntryKind::Special(_)) if !self.include_special => specials.push(entry),
+                _ => files.push(entry),
+            }
+        }
+
+        self.sort_entries(&mut files);
+        self.sort_entries(&mut sub_dirs);
+        self.sort_entries(&mut specials);
+
+        for entry in specials {
+            self.stats.files_special += 1;
+            if let Some(EntryKind::Special(kind)) = entry.metadata.map(|m| m.kind) {
+                self.app.do_special(Path::new(&entry.name), kind);
+            }
+        }
+
+        for entry in files {
+            if self.should_stop() {
+                break;
+            }
+            self.visit_file(&entry);
+        }
+
+        Ok(sub_dirs.into_iter().map(|d| d.path).collect())
+    }
+
+    /// Call do_file for a file of the current directory if it is selected
+    /// and passes the filters
+    fn visit_file(&mut self, entry: &Entry) {
+        if self.is_ignored(entry) {
+            self.stats.files_ignored += 1;
+            return;
+        }
+
+        self.stats.files_seen += 1;
+        if !self.is_selected(entry) {
+            return;
+        }
+
+        self.stats.files_matched += 1;
+        if self.passes_filters(entry) {
+            self.app.do_file(Path::new(&entry.name));
+        } else {
+            self.stats.files_filtered += 1;
+        }
+    }
+
+    /// Check whether the entry is one of the ignored files, only entries
+    /// with the name of one are made absolute
+    fn is_ignored(&self, entry: &Entry) -> bool {
+        let named = |file: &PathBuf| file.file_name() == Some(entry.name.as_os_str());
+        if !self.ignored_files.iter().any(named) {
+            return false;
+        }
+
+        match std::path::absolute(&entry.path) {
+            Ok(path) => self.ignored_files.contains(&path),
+            Err(_) => false,
+        }
+    }
+
+    /// True once the walk was stopped by `do_dir` or the app is done
+    fn should_stop(&self) -> bool {
+        self.stopped || self.app.done()
+    }
+
+    /// Sort directory entries according to the configured sort order
+    fn sort_entries(&self, entries: &mut [Entry]) {
+        match self.sort_order {
+            SortOrder::None => {}
+            SortOrder::Name => {
+                entries.sort_by(|a, b| natural_cmp(&a.name, &b.name));
+            }
+            SortOrder::Size => {
+                entries.sort_by_cached_key(|e| {
+                    (
+                        e.metadata.as_ref().map(|m| m.len).unwrap_or(0),
+                        NaturalKey(e.name.clone()),
+                    )
+                });
+            }
+            SortOrder::Modified => {
+                entries.sort_by_cached_key(|e| {
+                    (
+                        e.metadata.as_ref().and_then(|m| m.modified),
+                        NaturalKey(e.name.clone()),
+                    )
+                });
             }
-            return Ok(());
+        }
+    }
+
+    /// Check the entry metadata against all filters
+    fn passes_filters(&self, entry: &Entry) -> bool {
+        if self.filters.is_empty() {
+            return true;
         }
 
-        Err(Error::new(ErrorKind::Other, "Not a directory"))
+        match &entry.metadata {
+            Some(metadata) => self.filters.iter().all(|f| f.matches(metadata)),
+            None => false,
+        }
+    }
+
+    /// Check the entry against the patterns and the selected and excluded
+    /// types, everything is selected when neither patterns nor types are set
+    fn is_selected(&self, entry: &Entry) -> bool {
+        let selected = if self.patterns.is_empty() && self.selected_types.is_empty() {
+            true
+        } else {
+            self.has_pattern(&entry.path) || self.has_type(entry, &self.selected_types)
+        };
+
+        selected && !self.has_type(entry, &self.excluded_types)
+    }
+
+    /// Check if the entry is of any of the named types, extensionless
+    /// scripts are recognised by their `#!` line
+    fn has_type(&self, entry: &Entry, names: &[String]) -> bool {
+        if names.is_empty() {
+            return false;
+        }
+
+        let file_types: Vec<&FileType> = names
+            .iter()
+            .filter_map(|name| self.types.get(name).ok())
+            .collect();
+
+        let file_name = entry.name.to_string_lossy();
+
+        if file_types.iter().any(|t| t.matches_name(&file_name)) {
+            return true;
+        }
+
+        // only regular files are opened, a FIFO or device let through with
+        // special files may block or never end
+        if Path::new(&*file_name).extension().is_some()
+            || file_types.iter().all(|t| t.interpreters.is_empty())
+            || !entry.metadata.as_ref().is_some_and(|m| m.is_file())
+        {
+            return false;
+        }
+
+        match self.fs.open(&entry.path).ok().and_then(shebang_interpreter) {
+            Some(interpreter) => file_types
+                .iter()
+                .any(|t| t.interpreters.contains(&interpreter)),
+            None => false,
+        }
     }
 
     /// Check if the pattern exist in list of patterns to check
-    pub fn has_pattern(&self, directory: &DirEntry) -> bool {
-        let path = directory.path();
+    pub fn has_pattern(&self, path: &Path) -> bool {
         let extension = path.extension();
         match extension {
             Some(exist) => self.patterns.contains(&PathBuf::from(exist)),
@@ -128,6 +746,75 @@ impl<App: DirEvent + Default> DirNav<App> {
     }
 }
 
+/// Directory entry with the metadata needed to classify, sort and filter it
+struct Entry {
+    name: OsString,
+    path: PathBuf,
+    metadata: Option<EntryMetadata>,
+}
+
+/// Wrapper to order names naturally when used as part of a sort key
+#[derive(PartialEq, Eq)]
+struct NaturalKey(OsString);
+
+impl PartialOrd for NaturalKey {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for NaturalKey {
+    fn cmp(&self, other: &Self) -> Ordering {
+        natural_cmp(&self.0, &other.0)
+    }
+}
+
+/// Compare two names treating runs of digits as numbers, so that
+/// `file2` sorts before `file10`. Falls back to byte order on ties.
+pub fn natural_cmp(a: &OsStr, b: &OsStr) -> Ordering {
+    let a_str = a.to_string_lossy();
+    let b_str = b.to_string_lossy();
+    let mut a_chars = a_str.chars().peekable();
+    let mut b_chars = b_str.chars().peekable();
+
+    loop {
+        match (a_chars.peek(), b_chars.peek()) {
+            (None, None) => return a.cmp(b),
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(x), Some(y)) if x.is_ascii_digit() && y.is_ascii_digit() => {
+                let mut x_num = String::new();
+                while let Some(c) = a_chars.next_if(|c| c.is_ascii_digit()) {
+                    x_num.push(c);
+                }
+                let mut y_num = String::new();
+                while let Some(c) = b_chars.next_if(|c| c.is_ascii_digit()) {
+                    y_num.push(c);
+                }
+
+                // Compare numerically without overflow, ignoring leading zeros
+                let x_trim = x_num.trim_start_matches('0');
+                let y_trim = y_num.trim_start_matches('0');
+                let ord = x_trim
+                    .len()
+                    .cmp(&y_trim.len())
+                    .then_with(|| x_trim.cmp(y_trim));
+                if ord != Ordering::Equal {
+                    return ord;
+                }
+            }
+            (Some(x), Some(y)) => {
+                let ord = x.cmp(y);
+                if ord != Ordering::Equal {
+                    return ord;
+                }
+                a_chars.next();
+                b_chars.next();
+            }
+        }
+    }
+}
+
 impl<App: DirEvent + Default> Default for DirNav<App> {
     fn default() -> Self {
         Self::new()
@@ -137,26 +824,39 @@ impl<App: DirEvent + Default> Default for DirNav<App> {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::panic;
 
     struct Test {
         results: Vec<PathBuf>,
+        fs: Arc<dyn FileSystem>,
     }
 
     impl DirEvent for Test {
         fn new() -> Self {
             Self {
                 results: Vec::<PathBuf>::new(),
+                fs: Arc::new(StdFileSystem),
             }
         }
 
-        fn do_dir(&mut self, _d: &Path) {
-            // Do nothing
+        fn do_dir(&mut self, d: &Path) -> DirAction {
+            if self.fs.metadata(&d.join(".textfinder-skip")).is_ok() {
+                return DirAction::SkipChildren;
+            }
+
+            if self.fs.metadata(&d.join(".textfinder-stop")).is_ok() {
+                return DirAction::Stop;
+            }
+
+            DirAction::Descend
         }
 
         fn do_file(&mut self, f: &Path) {
             self.results.push(PathBuf::from(f));
         }
+
+        fn set_file_system(&mut self, fs: Arc<dyn FileSystem>) {
+            self.fs = fs;
+        }
     }
 
     impl Default for Test {
@@ -165,26 +865,37 @@ mod tests {
         }
     }
 
-    fn setup() {
-        let _ = std::fs::create_dir("./test_dir");
-        let _ = std::fs::create_dir("./test_dir/test_sub_dir1");
-        let _ = std::fs::create_dir("./test_dir/test_sub_dir2");
-        let _ = std::fs::create_dir("./test_dir/test_sub_dir3");
-        let _ = std::fs::File::create("./test_dir/test_file.rs");
-        let _ = std::fs::File::create("./test_dir/test_sub_dir1/test_file1.rs");
-        let _ = std::fs::File::create("./test_dir/test_sub_dir1/test_file2.bin");
-        let _ = std::fs::File::create("./test_dir/test_sub_dir2/test_file3.txt");
-        let _ = std::fs::File::create("./test_dir/test_sub_dir3/test_file4.out");
+    /// Fresh in-memory copy of the test tree, tests running in parallel
+    /// never share or modify each other's fixture
+    fn setup() -> MemoryFileSystem {
+        let mut fs = MemoryFileSystem::new();
+        fs.add_dir(Path::new("./test_dir/test_sub_dir1"));
+        fs.add_dir(Path::new("./test_dir/test_sub_dir2"));
+        fs.add_dir(Path::new("./test_dir/test_sub_dir3"));
+        fs.add_file(Path::new("./test_dir/test_file.rs"), b"");
+        fs.add_file(Path::new("./test_dir/test_sub_dir1/test_file1.rs"), b"");
+        fs.add_file(Path::new("./test_dir/test_sub_dir1/test_file2.bin"), b"");
+        fs.add_file(Path::new("./test_dir/test_sub_dir2/test_file3.txt"), b"");
+        fs.add_file(Path::new("./test_dir/test_sub_dir3/test_file4.out"), b"");
+        fs
     }
 
-    fn norecurse() {
+    /// DirNav walking the given in-memory tree
+    fn dir_nav(fs: &MemoryFileSystem) -> DirNav<Test> {
         let mut directory_nav = DirNav::<Test>::new();
+        directory_nav.set_file_system(Arc::new(fs.clone()));
+        directory_nav
+    }
+
+    #[test]
+    fn test_norecurse() {
+        let mut directory_nav = dir_nav(&setup());
         directory_nav.add_patterns(&Path::new("rs"));
         directory_nav.add_patterns(&Path::new("bin"));
         directory_nav.add_patterns(&Path::new("out"));
 
         let mut path = PathBuf::new();
-        path.push("./test_dir".to_string());
+        path.push("./test_dir");
 
         let res = directory_nav.visit(&path);
         assert!(res.is_ok(), "Visit received error {:#?}", res.err());
@@ -194,14 +905,15 @@ mod tests {
         let path = |s: &str| -> PathBuf { PathBuf::from(s) };
 
         assert!(res.contains(&path("test_file.rs")));
-        assert_eq!(res.contains(&path("test_file1.rs")), false);
-        assert_eq!(res.contains(&path("test_file2.bin")), false);
-        assert_eq!(res.contains(&path("test_file3.txt")), false);
-        assert_eq!(res.contains(&path("test_file4.out")), false);
+        assert!(!res.contains(&path("test_file1.rs")));
+        assert!(!res.contains(&path("test_file2.bin")));
+        assert!(!res.contains(&path("test_file3.txt")));
+        assert!(!res.contains(&path("test_file4.out")));
     }
 
-    fn recurse() {
-        let mut directory_nav = DirNav::<Test>::new();
+    #[test]
+    fn test_recurse() {
+        let mut directory_nav = dir_nav(&setup());
         directory_nav.add_patterns(&Path::new("rs"));
         directory_nav.add_patterns(&Path::new("bin"));
         directory_nav.add_patterns(&Path::new("out"));
@@ -209,7 +921,7 @@ mod tests {
         directory_nav.set_recursive_search();
 
         let mut path = PathBuf::new();
-        path.push("./test_dir".to_string());
+        path.push("./test_dir");
 
         let res = directory_nav.visit(&path);
         assert!(res.is_ok(), "Visit received error {:#?}", res.err());
@@ -224,10 +936,11 @@ mod tests {
         assert!(res.contains(&path("test_file4.out")));
 
         // txt was not part of pattern
-        assert_eq!(res.contains(&path("test_file3.txt")), false);
+        assert!(!res.contains(&path("test_file3.txt")));
     }
 
-    fn searchpatterns() {
+    #[test]
+    fn test_searchpatterns() {
         let mut directory_nav = DirNav::<Test>::new();
         directory_nav.add_patterns(&Path::new("rs"));
         directory_nav.add_patterns(&Path::new("bin"));
@@ -240,41 +953,541 @@ mod tests {
         assert_eq!(directory_nav.patterns.len(), 0);
     }
 
-    fn teardown() {
-        let _ = std::fs::remove_dir_all("./test_dir");
+    #[test]
+    fn test_sort_name() {
+        let mut fs = setup();
+        fs.add_file(Path::new("./test_dir/test_sub_dir3/file10.rs"), b"");
+        fs.add_file(Path::new("./test_dir/test_sub_dir3/file2.rs"), b"");
+        fs.add_file(Path::new("./test_dir/test_sub_dir3/file1.rs"), b"");
+
+        let mut directory_nav = dir_nav(&fs);
+        directory_nav.add_patterns(Path::new("rs"));
+        directory_nav.set_sort_order(SortOrder::Name);
+
+        let res = directory_nav.visit(Path::new("./test_dir/test_sub_dir3"));
+        assert!(res.is_ok(), "Visit received error {:#?}", res.err());
+
+        let res = &directory_nav.get_app().results;
+
+        assert_eq!(
+            *res,
+            vec![
+                PathBuf::from("file1.rs"),
+                PathBuf::from("file2.rs"),
+                PathBuf::from("file10.rs")
+            ]
+        );
     }
 
-    fn run_test<T>(test: T) -> ()
-    where
-        T: FnOnce() -> () + panic::UnwindSafe,
-    {
-        setup();
+    #[test]
+    fn test_sort_size() {
+        let mut fs = setup();
+        fs.add_file(Path::new("./test_dir/test_sub_dir3/big.rs"), b"xxxx");
+        fs.add_file(Path::new("./test_dir/test_sub_dir3/small.rs"), b"x");
 
-        let result = panic::catch_unwind(|| test());
+        let mut directory_nav = dir_nav(&fs);
+        directory_nav.set_sort_order(SortOrder::Size);
 
-        teardown();
+        let res = directory_nav.visit(Path::new("./test_dir/test_sub_dir3"));
+        assert!(res.is_ok(), "Visit received error {:#?}", res.err());
 
-        assert!(result.is_ok())
+        // empty test_file4.out first, ties are broken by name
+        assert_eq!(
+            directory_nav.get_app().results,
+            vec![
+                PathBuf::from("test_file4.out"),
+                PathBuf::from("small.rs"),
+                PathBuf::from("big.rs")
+            ]
+        );
     }
 
     #[test]
-    fn test_norecurse() {
-        run_test(|| {
-            norecurse();
-        })
+    fn test_sort_modified() {
+        let mut fs = setup();
+        for (name, secs) in [
+            ("new.rs", 300),
+            ("old.rs", 100),
+            ("mid.rs", 200),
+            ("test_file4.out", 200),
+        ] {
+            let path = Path::new("./test_dir/test_sub_dir3").join(name);
+            fs.add_file(&path, b"");
+            let mut metadata = EntryMetadata::new(EntryKind::File, 0);
+            metadata.modified =
+                std::time::UNIX_EPOCH.checked_add(std::time::Duration::from_secs(secs));
+            fs.set_metadata(&path, metadata).unwrap();
+        }
+
+        let mut directory_nav = dir_nav(&fs);
+        directory_nav.set_sort_order(SortOrder::Modified);
+
+        let res = directory_nav.visit(Path::new("./test_dir/test_sub_dir3"));
+        assert!(res.is_ok(), "Visit received error {:#?}", res.err());
+
+        // oldest first, ties are broken by name
+        assert_eq!(
+            directory_nav.get_app().results,
+            vec![
+                PathBuf::from("old.rs"),
+                PathBuf::from("mid.rs"),
+                PathBuf::from("test_file4.out"),
+                PathBuf::from("new.rs")
+            ]
+        );
     }
 
     #[test]
-    fn test_recurse() {
-        run_test(|| {
-            recurse();
-        })
+    fn test_breadth_first() {
+        let mut fs = setup();
+        fs.add_file(
+            Path::new("./test_dir/test_sub_dir1/test_deep_dir/test_file5.rs"),
+            b"",
+        );
+
+        let mut directory_nav = dir_nav(&fs);
+        directory_nav.set_recursive_search();
+        directory_nav.set_sort_order(SortOrder::Name);
+        directory_nav.set_traversal_mode(TraversalMode::BreadthFirst);
+
+        let res = directory_nav.visit(Path::new("./test_dir"));
+        assert!(res.is_ok(), "Visit received error {:#?}", res.err());
+
+        let expected: Vec<PathBuf> = [
+            "test_file.rs",
+            "test_file1.rs",
+            "test_file2.bin",
+            "test_file3.txt",
+            "test_file4.out",
+            "test_file5.rs",
+        ]
+        .iter()
+        .map(PathBuf::from)
+        .collect();
+
+        // shallow files come first, in name order within a level
+        assert_eq!(directory_nav.get_app().results, expected);
+        assert_eq!(directory_nav.get_number_of_dirs_processed(), 5);
     }
 
     #[test]
-    fn test_searchpatterns() {
-        run_test(|| {
-            searchpatterns();
-        })
+    fn test_pruning() {
+        let mut fs = MemoryFileSystem::new();
+        fs.add_file(Path::new("./test_prune_dir/skip/.textfinder-skip"), b"");
+        fs.add_file(Path::new("./test_prune_dir/skip/skipped.rs"), b"");
+        fs.add_dir(Path::new("./test_prune_dir/stop"));
+        fs.add_file(Path::new("./test_prune_dir/zzz/after_stop.rs"), b"");
+        fs.add_file(Path::new("./test_prune_dir/top.rs"), b"");
+
+        let mut directory_nav = dir_nav(&fs);
+        directory_nav.set_recursive_search();
+        directory_nav.set_sort_order(SortOrder::Name);
+
+        let res = directory_nav.visit(Path::new("./test_prune_dir"));
+        assert!(res.is_ok(), "Visit received error {:#?}", res.err());
+
+        // skip was pruned, neither its files nor the marker are visited
+        assert_eq!(
+            directory_nav.get_app().results,
+            vec![PathBuf::from("top.rs"), PathBuf::from("after_stop.rs")]
+        );
+
+        fs.add_file(Path::new("./test_prune_dir/stop/.textfinder-stop"), b"");
+        let mut directory_nav = dir_nav(&fs);
+        directory_nav.set_recursive_search();
+        directory_nav.set_sort_order(SortOrder::Name);
+
+        let res = directory_nav.visit(Path::new("./test_prune_dir"));
+        assert!(res.is_ok(), "Visit received error {:#?}", res.err());
+
+        // walk stopped at stop, zzz is never reached
+        assert_eq!(
+            directory_nav.get_app().results,
+            vec![PathBuf::from("top.rs")]
+        );
+
+        // also when stop overflows the breadth-first queue and is walked
+        // depth-first, zzz after it is not handed to do_dir
+        let mut directory_nav = dir_nav(&fs);
+        directory_nav.set_recursive_search();
+        directory_nav.set_sort_order(SortOrder::Name);
+        directory_nav.set_traversal_mode(TraversalMode::BreadthFirst);
+        directory_nav.set_queue_limit(1);
+
+        let res = directory_nav.visit(Path::new("./test_prune_dir"));
+        assert!(res.is_ok(), "Visit received error {:#?}", res.err());
+        assert_eq!(
+            directory_nav.get_app().results,
+            vec![PathBuf::from("top.rs")]
+        );
+        assert_eq!(directory_nav.get_number_of_dirs_processed(), 2);
+    }
+
+    #[test]
+    fn test_filters() {
+        let mut fs = MemoryFileSystem::new();
+        fs.add_file(Path::new("./test_filter_dir/big.log"), &[b'x'; 4096]);
+        fs.add_file(Path::new("./test_filter_dir/small.log"), b"x");
+
+        let mut directory_nav = dir_nav(&fs);
+        directory_nav.add_filter(FileFilter::size("+1k").unwrap());
+
+        let res = directory_nav.visit(Path::new("./test_filter_dir"));
+        assert!(res.is_ok(), "Visit received error {:#?}", res.err());
+        assert_eq!(
+            directory_nav.get_app().results,
+            vec![PathBuf::from("big.log")]
+        );
+
+        let mut directory_nav = dir_nav(&fs);
+        directory_nav.add_filter(FileFilter::modified("1h", true).unwrap());
+        directory_nav.add_filter(FileFilter::size("-1k").unwrap());
+
+        let res = directory_nav.visit(Path::new("./test_filter_dir"));
+        assert!(res.is_ok(), "Visit received error {:#?}", res.err());
+        assert_eq!(
+            directory_nav.get_app().results,
+            vec![PathBuf::from("small.log")]
+        );
+
+        let mut directory_nav = dir_nav(&fs);
+        directory_nav.add_filter(FileFilter::modified("1h", false).unwrap());
+
+        let res = directory_nav.visit(Path::new("./test_filter_dir"));
+        assert!(res.is_ok(), "Visit received error {:#?}", res.err());
+        assert!(directory_nav.get_app().results.is_empty());
+    }
+
+    #[test]
+    fn test_one_file_system() {
+        let mut fs = setup();
+        for (dir, device) in [
+            ("./test_dir", 1),
+            ("./test_dir/test_sub_dir1", 1),
+            ("./test_dir/test_sub_dir2", 1),
+            ("./test_dir/test_sub_dir3", 2),
+        ] {
+            let mut metadata = EntryMetadata::new(EntryKind::Dir, 0);
+            metadata.device = Some(device);
+            fs.set_metadata(Path::new(dir), metadata).unwrap();
+        }
+
+        let mut directory_nav = dir_nav(&fs);
+        directory_nav.set_recursive_search();
+        directory_nav.set_one_file_system();
+
+        // test_sub_dir3 is mounted from another device
+        let res = directory_nav.visit(Path::new("./test_dir"));
+        assert!(res.is_ok(), "Visit received error {:#?}", res.err());
+        assert_eq!(
+            *directory_nav.get_skipped_mount_points(),
+            vec![PathBuf::from("./test_dir/test_sub_dir3")]
+        );
+
+        let res = &directory_nav.get_app().results;
+        assert!(res.contains(&PathBuf::from("test_file3.txt")));
+        assert!(!res.contains(&PathBuf::from("test_file4.out")));
+
+        // a directory that vanished after it was listed is an error, not a
+        // mount point
+        let mut directory_nav = DirNav::<Test>::new();
+        directory_nav.set_file_system(Arc::new(Vanishing {
+            fs: fs.clone(),
+            dir: PathBuf::from("./test_dir/test_sub_dir2"),
+            calls: std::sync::Mutex::new(0),
+        }));
+        directory_nav.set_recursive_search();
+        directory_nav.set_one_file_system();
+
+        let res = directory_nav.visit(Path::new("./test_dir"));
+        assert!(res.is_ok(), "Visit received error {:#?}", res.err());
+        assert_eq!(
+            *directory_nav.get_skipped_mount_points(),
+            vec![PathBuf::from("./test_dir/test_sub_dir3")]
+        );
+        assert_eq!(directory_nav.get_stats().errors, 1);
+        assert_eq!(directory_nav.get_stats().dirs_pruned, 1);
+    }
+
+    /// File system whose metadata of one directory fails once it was read
+    /// while listing its parent
+    struct Vanishing {
+        fs: MemoryFileSystem,
+        dir: PathBuf,
+        calls: std::sync::Mutex<usize>,
+    }
+
+    impl FileSystem for Vanishing {
+        fn read_dir(&self, dir: &Path) -> io::Result<Vec<OsString>> {
+            self.fs.read_dir(dir)
+        }
+
+        fn metadata(&self, path: &Path) -> io::Result<EntryMetadata> {
+            if path == self.dir {
+                let mut calls = self.calls.lock().unwrap();
+                *calls += 1;
+                if *calls > 1 {
+                    return Err(io::Error::new(io::ErrorKind::NotFound, "vanished"));
+                }
+            }
+            self.fs.metadata(path)
+        }
+
+        fn open(&self, path: &Path) -> io::Result<Box<dyn std::io::Read + Send>> {
+            self.fs.open(path)
+        }
+    }
+
+    #[test]
+    fn test_special_files() {
+        let mut fs = MemoryFileSystem::new();
+        fs.add_file(Path::new("./test_special_dir/regular.txt"), b"");
+        fs.add_special(Path::new("./test_special_dir/pipe"), SpecialFile::Fifo);
+        fs.add_special(Path::new("./test_special_dir/socket"), SpecialFile::Socket);
+
+        let mut directory_nav = dir_nav(&fs);
+
+        let res = directory_nav.visit(Path::new("./test_special_dir"));
+        assert!(res.is_ok(), "Visit received error {:#?}", res.err());
+
+        // the FIFO and the socket never reach do_file
+        assert_eq!(
+            directory_nav.get_app().results,
+            vec![PathBuf::from("regular.txt")]
+        );
+        assert_eq!(directory_nav.get_number_of_special_files(), 2);
+        assert_eq!(directory_nav.get_number_of_files_processed(), 1);
+    }
+
+    /// File system failing to list one directory
+    struct Unreadable {
+        fs: MemoryFileSystem,
+        dir: PathBuf,
+    }
+
+    impl FileSystem for Unreadable {
+        fn read_dir(&self, dir: &Path) -> io::Result<Vec<OsString>> {
+            if dir == self.dir {
+                return Err(io::Error::new(io::ErrorKind::PermissionDenied, "denied"));
+            }
+            self.fs.read_dir(dir)
+        }
+
+        fn metadata(&self, path: &Path) -> io::Result<EntryMetadata> {
+            self.fs.metadata(path)
+        }
+
+        fn open(&self, path: &Path) -> io::Result<Box<dyn std::io::Read + Send>> {
+            self.fs.open(path)
+        }
+    }
+
+    #[test]
+    fn test_unreadable_dirs() {
+        for mode in [TraversalMode::DepthFirst, TraversalMode::BreadthFirst] {
+            let mut directory_nav = DirNav::<Test>::new();
+            directory_nav.set_file_system(Arc::new(Unreadable {
+                fs: setup(),
+                dir: PathBuf::from("./test_dir/test_sub_dir1"),
+            }));
+            directory_nav.set_traversal_mode(mode);
+            directory_nav.set_recursive_search();
+            directory_nav.add_patterns(Path::new("txt"));
+            directory_nav.add_patterns(Path::new("out"));
+
+            // the walk goes on past the unreadable subdirectory
+            let res = directory_nav.visit(Path::new("./test_dir"));
+            assert!(res.is_ok(), "Visit received error {:#?}", res.err());
+            assert_eq!(directory_nav.get_stats().errors, 1);
+            assert_eq!(directory_nav.get_app().results.len(), 2);
+
+            // but not an unreadable root
+            let res = directory_nav.visit(Path::new("./test_dir/test_sub_dir1"));
+            assert_eq!(res.unwrap_err().kind(), io::ErrorKind::PermissionDenied);
+        }
+    }
+
+    #[test]
+    fn test_traversal_stats() {
+        let mut directory_nav = dir_nav(&setup());
+        directory_nav.add_patterns(Path::new("rs"));
+        directory_nav.add_filter(FileFilter::size("+1k").unwrap());
+
+        let res = directory_nav.visit(Path::new("./test_dir/test_sub_dir1"));
+        assert!(res.is_ok(), "Visit received error {:#?}", res.err());
+
+        let stats = directory_nav.get_stats();
+
+        // test_file1.rs matches the pattern but is empty, test_file2.bin does not
+        assert_eq!(stats.dirs_visited, 1);
+        assert_eq!(stats.files_seen, 2);
+        assert_eq!(stats.files_matched, 1);
+        assert_eq!(stats.files_filtered, 1);
+        assert_eq!(stats.files_skipped(), 2);
+        assert!(directory_nav.get_app().results.is_empty());
+
+        // an ignored file is counted apart from the files seen
+        let mut directory_nav = dir_nav(&setup());
+        directory_nav.add_ignored_file(Path::new("./test_dir/test_sub_dir1/test_file2.bin"));
+
+        let res = directory_nav.visit(Path::new("./test_dir/test_sub_dir1"));
+        assert!(res.is_ok(), "Visit received error {:#?}", res.err());
+
+        let stats = directory_nav.get_stats();
+        assert_eq!(stats.files_seen, 1);
+        assert_eq!(stats.files_ignored, 1);
+        assert_eq!(stats.files_skipped(), 1);
+        assert!(stats.to_string().contains("ignored 1"));
+        assert_eq!(directory_nav.get_app().results.len(), 1);
+    }
+
+    #[test]
+    fn test_file_types() {
+        let mut fs = MemoryFileSystem::new();
+        fs.add_file(
+            Path::new("./test_type_dir/build"),
+            b"#!/usr/bin/env python3\n",
+        );
+        fs.add_file(Path::new("./test_type_dir/run"), b"#!/bin/bash -e\n");
+        fs.add_file(Path::new("./test_type_dir/Makefile"), b"");
+        fs.add_file(Path::new("./test_type_dir/main.rs"), b"");
+        fs.add_file(Path::new("./test_type_dir/README.md"), b"");
+
+        let mut directory_nav = dir_nav(&fs);
+        directory_nav.set_sort_order(SortOrder::Name);
+        assert!(directory_nav.add_type("python").is_ok());
+        assert!(directory_nav.add_type("make").is_ok());
+        assert!(directory_nav.add_type("does-not-exist").is_err());
+
+        let res = directory_nav.visit(Path::new("./test_type_dir"));
+        assert!(res.is_ok(), "Visit received error {:#?}", res.err());
+        assert_eq!(
+            directory_nav.get_app().results,
+            vec![PathBuf::from("Makefile"), PathBuf::from("build")]
+        );
+
+        let mut directory_nav = dir_nav(&fs);
+        directory_nav.set_sort_order(SortOrder::Name);
+        assert!(directory_nav.add_type_not("markdown").is_ok());
+        assert!(directory_nav.add_type_not("sh").is_ok());
+
+        let res = directory_nav.visit(Path::new("./test_type_dir"));
+        assert!(res.is_ok(), "Visit received error {:#?}", res.err());
+        assert_eq!(
+            directory_nav.get_app().results,
+            vec![
+                PathBuf::from("Makefile"),
+                PathBuf::from("build"),
+                PathBuf::from("main.rs")
+            ]
+        );
+
+        // a FIFO is never opened to look for a `#!` line
+        let mut fs = fs.clone();
+        fs.add_file(Path::new("./test_type_dir/pipe"), b"#!/bin/sh\n");
+        let fifo = EntryMetadata::new(EntryKind::Special(SpecialFile::Fifo), 0);
+        fs.set_metadata(Path::new("./test_type_dir/pipe"), fifo)
+            .unwrap();
+
+        let mut directory_nav = dir_nav(&fs);
+        directory_nav.set_include_special_files();
+        assert!(directory_nav.add_type("sh").is_ok());
+
+        let res = directory_nav.visit(Path::new("./test_type_dir"));
+        assert!(res.is_ok(), "Visit received error {:#?}", res.err());
+        assert_eq!(directory_nav.get_app().results, vec![PathBuf::from("run")]);
+    }
+
+    #[test]
+    fn test_archives() {
+        let mut builder = tar::Builder::new(Vec::new());
+        let mut header = tar::Header::new_gnu();
+        header.set_size(4);
+        header.set_mode(0o644);
+        header.set_cksum();
+        builder
+            .append_data(&mut header, "logs/app.log", &b"boom"[..])
+            .unwrap();
+
+        let mut fs = setup();
+        fs.add_file(
+            Path::new("./test_dir/bundle.tar"),
+            &builder.into_inner().unwrap(),
+        );
+
+        let mut directory_nav = dir_nav(&fs);
+        directory_nav.set_recursive_search();
+        directory_nav.set_search_archives();
+        directory_nav.add_patterns(Path::new("log"));
+
+        let res = directory_nav.visit(Path::new("./test_dir"));
+        assert!(res.is_ok(), "Visit received error {:#?}", res.err());
+        assert_eq!(
+            directory_nav.get_app().results,
+            vec![PathBuf::from("app.log")]
+        );
+
+        // the archive itself is a directory now, not a file
+        assert_eq!(directory_nav.get_number_of_files_processed(), 6);
+
+        // an archive over the limits is skipped, not an error
+        let mut directory_nav = dir_nav(&fs);
+        directory_nav.set_recursive_search();
+        let archives = ArchiveFileSystem::new(Arc::new(fs.clone())).with_limits(2, 2);
+        directory_nav.set_file_system(Arc::new(archives));
+
+        let res = directory_nav.visit(Path::new("./test_dir"));
+        assert!(res.is_ok(), "Visit received error {:#?}", res.err());
+        assert_eq!(directory_nav.get_stats().files_too_big, 1);
+        assert_eq!(directory_nav.get_stats().errors, 0);
+    }
+
+    #[test]
+    fn test_visit_files() {
+        let mut directory_nav = dir_nav(&setup());
+        directory_nav.add_patterns(Path::new("rs"));
+        directory_nav.add_patterns(Path::new("txt"));
+
+        directory_nav.visit_files(
+            [
+                "./test_dir/test_file.rs",
+                "./test_dir/test_sub_dir1/test_file1.rs",
+                "./test_dir/test_sub_dir1/test_file2.bin",
+                "./test_dir/missing.rs",
+                "./test_dir/test_sub_dir2",
+                "./test_dir/test_sub_dir2/test_file3.txt",
+            ]
+            .iter()
+            .map(PathBuf::from),
+        );
+
+        // the bin file is not in the patterns, the missing file and the
+        // directory are errors
+        assert_eq!(
+            directory_nav.get_app().results,
+            vec![
+                PathBuf::from("test_file.rs"),
+                PathBuf::from("test_file1.rs"),
+                PathBuf::from("test_file3.txt")
+            ]
+        );
+
+        let stats = directory_nav.get_stats();
+        assert_eq!(stats.files_seen, 4);
+        assert_eq!(stats.errors, 2);
+        assert_eq!(stats.dirs_visited, 4);
+    }
+
+    #[test]
+    fn test_natural_cmp() {
+        let cmp = |a: &str, b: &str| natural_cmp(OsStr::new(a), OsStr::new(b));
+
+        assert_eq!(cmp("file2", "file10"), Ordering::Less);
+        assert_eq!(cmp("file10", "file2"), Ordering::Greater);
+        assert_eq!(cmp("a", "b"), Ordering::Less);
+        assert_eq!(cmp("v1.2.10", "v1.2.9"), Ordering::Greater);
+        assert_eq!(cmp("file", "file1"), Ordering::Less);
+        assert_eq!(cmp("file01", "file1"), Ordering::Less);
+        assert_eq!(cmp("same", "same"), Ordering::Equal);
     }
 }
@@ -0,0 +1,263 @@
+use crate::EntryMetadata;
+use std::cmp::Ordering;
+#[cfg(unix)]
+use std::ffi::CString;
+use std::fs;
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+#[cfg(unix)]
+use std::{mem, ptr};
+
+/// Find style predicate evaluated on the metadata of a file before it is
+/// handed to `DirEvent::do_file`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FileFilter {
+    /// File size compared against a number of bytes
+    Size(Ordering, u64),
+    /// Modification time, `Greater` is newer and `Less` is older than the
+    /// reference
+    Modified(Ordering, SystemTime),
+    /// Status change time, `Greater` is newer and `Less` is older than the
+    /// reference
+    Changed(Ordering, SystemTime),
+    /// Numeric user id of the owner
+    Owner(u32),
+    /// Numeric group id of the owner
+    Group(u32),
+    /// Any of the executable bits is set
+    Executable,
+}
+
+impl FileFilter {
+    /// Parse a size range such as `+10M` (more than), `-1k` (less than) or
+    /// `512` (exactly), units are `b`, `k`, `M` and `G` (powers of 1024)
+    pub fn size(spec: &str) -> Result<Self, String> {
+        let (ordering, rest) = split_sign(spec);
+
+        let (digits, unit) = match rest.find(|c: char| !c.is_ascii_digit()) {
+            Some(pos) => rest.split_at(pos),
+            None => (rest, ""),
+        };
+
+        let scale: u64 = match unit {
+            "" | "b" | "c" => 1,
+            "k" | "K" => 1 << 10,
+            "M" => 1 << 20,
+            "G" => 1 << 30,
+            _ => return Err(format!("invalid size unit in `{}`, use b, k, M or G", spec)),
+        };
+
+        let value = digits
+            .parse::<u64>()
+            .map_err(|_| format!("invalid size `{}`, expected e.g. `+10M` or `-1k`", spec))?;
+
+        Ok(FileFilter::Size(ordering, value.saturating_mul(scale)))
+    }
+
+    /// Modified newer (or older) than a duration ago such as `2h`, or than
+    /// the modification time of a reference file
+    pub fn modified(spec: &str, newer: bool) -> Result<Self, String> {
+        Ok(FileFilter::Modified(
+            newer_or_older(newer),
+            reference_time(spec)?,
+        ))
+    }
+
+    /// Changed newer (or older) than a duration ago such as `2h`, or than
+    /// the modification time of a reference file
+    pub fn changed(spec: &str, newer: bool) -> Result<Self, String> {
+        Ok(FileFilter::Changed(
+            newer_or_older(newer),
+            reference_time(spec)?,
+        ))
+    }
+
+    /// Owner given as user name or numeric user id
+    pub fn owner(spec: &str) -> Result<Self, String> {
+        spec.parse::<u32>()
+            .ok()
+            .or_else(|| lookup_user(spec))
+            .map(FileFilter::Owner)
+            .ok_or_else(|| format!("unknown user `{}`", spec))
+    }
+
+    /// Group given as group name or numeric group id
+    pub fn group(spec: &str) -> Result<Self, String> {
+        spec.parse::<u32>()
+            .ok()
+            .or_else(|| lookup_group(spec))
+            .map(FileFilter::Group)
+            .ok_or_else(|| format!("unknown group `{}`", spec))
+    }
+
+    /// Check whether a file with the given metadata passes the filter
+    pub fn matches(&self, metadata: &EntryMetadata) -> bool {
+        match self {
+            FileFilter::Size(ordering, bytes) => metadata.len.cmp(bytes) == *ordering,
+            FileFilter::Modified(ordering, time) => {
+                metadata.modified.is_some_and(|m| m.cmp(time) == *ordering)
+            }
+            FileFilter::Changed(ordering, time) => {
+                metadata.changed.is_some_and(|c| c.cmp(time) == *ordering)
+            }
+            FileFilter::Owner(uid) => metadata.uid == Some(*uid),
+            FileFilter::Group(gid) => metadata.gid == Some(*gid),
+            FileFilter::Executable => {
+                metadata.is_file() && metadata.mode.is_some_and(|m| m & 0o111 != 0)
+            }
+        }
+    }
+}
+
+/// Parse a duration such as `30s`, `15m`, `2h`, `7d` or `1w`, a bare number
+/// is taken as seconds
+pub fn parse_duration(spec: &str) -> Option<Duration> {
+    let (digits, unit) = match spec.find(|c: char| !c.is_ascii_digit()) {
+        Some(pos) => spec.split_at(pos),
+        None => (spec, "s"),
+    };
+
+    let scale: u64 = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        "w" => 7 * 24 * 60 * 60,
+        _ => return None,
+    };
+
+    let value = digits.parse::<u64>().ok()?;
+    Some(Duration::from_secs(value.saturating_mul(scale)))
+}
+
+/// Split a leading `+` or `-` into the ordering it stands for
+fn split_sign(spec: &str) -> (Ordering, &str) {
+    if let Some(rest) = spec.strip_prefix('+') {
+        (Ordering::Greater, rest)
+    } else if let Some(rest) = spec.strip_prefix('-') {
+        (Ordering::Less, rest)
+    } else {
+        (Ordering::Equal, spec)
+    }
+}
+
+fn newer_or_older(newer: bool) -> Ordering {
+    if newer {
+        Ordering::Greater
+    } else {
+        Ordering::Less
+    }
+}
+
+/// Resolve a duration ago, or the modification time of a reference file
+fn reference_time(spec: &str) -> Result<SystemTime, String> {
+    if let Some(duration) = parse_duration(spec) {
+        return SystemTime::now()
+            .checked_sub(duration)
+            .ok_or_else(|| format!("duration `{}` is too large", spec));
+    }
+
+    fs::metadata(Path::new(spec))
+        .and_then(|m| m.modified())
+        .map_err(|_| {
+            format!(
+                "`{}` is neither a duration (e.g. `2h`, `7d`) nor a readable file",
+                spec
+            )
+        })
+}
+
+/// Resolve a user name through the system user database, so NSS sources
+/// such as LDAP or sssd are honored
+#[cfg(unix)]
+fn lookup_user(name: &str) -> Option<u32> {
+    let name = CString::new(name).ok()?;
+    lookup_entry(|buf, len| {
+        let mut pwd: libc::passwd = unsafe { mem::zeroed() };
+        let mut result = ptr::null_mut();
+        let ret = unsafe { libc::getpwnam_r(name.as_ptr(), &mut pwd, buf, len, &mut result) };
+        (ret, (!result.is_null()).then_some(pwd.pw_uid))
+    })
+}
+
+/// Resolve a group name through the system group database
+#[cfg(unix)]
+fn lookup_group(name: &str) -> Option<u32> {
+    let name = CString::new(name).ok()?;
+    lookup_entry(|buf, len| {
+        let mut grp: libc::group = unsafe { mem::zeroed() };
+        let mut result = ptr::null_mut();
+        let ret = unsafe { libc::getgrnam_r(name.as_ptr(), &mut grp, buf, len, &mut result) };
+        (ret, (!result.is_null()).then_some(grp.gr_gid))
+    })
+}
+
+/// Call a reentrant `get*nam_r` lookup, growing the string buffer while it
+/// reports `ERANGE`
+#[cfg(unix)]
+fn lookup_entry(
+    mut lookup: impl FnMut(*mut libc::c_char, libc::size_t) -> (libc::c_int, Option<u32>),
+) -> Option<u32> {
+    let mut buf = vec![0 as libc::c_char; 1024];
+    loop {
+        match lookup(buf.as_mut_ptr(), buf.len()) {
+            (libc::ERANGE, _) if buf.len() < 1 << 20 => buf.resize(buf.len() * 2, 0),
+            (0, id) => return id,
+            _ => return None,
+        }
+    }
+}
+
+/// Names can't be resolved without a user database, only numeric ids work
+#[cfg(not(unix))]
+fn lookup_user(_name: &str) -> Option<u32> {
+    None
+}
+
+#[cfg(not(unix))]
+fn lookup_group(_name: &str) -> Option<u32> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_size() {
+        assert_eq!(
+            FileFilter::size("+10M"),
+            Ok(FileFilter::Size(Ordering::Greater, 10 * 1024 * 1024))
+        );
+        assert_eq!(
+            FileFilter::size("-1k"),
+            Ok(FileFilter::Size(Ordering::Less, 1024))
+        );
+        assert_eq!(
+            FileFilter::size("512"),
+            Ok(FileFilter::Size(Ordering::Equal, 512))
+        );
+        assert!(FileFilter::size("+10X").is_err());
+        assert!(FileFilter::size("+").is_err());
+    }
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("30"), Some(Duration::from_secs(30)));
+        assert_eq!(parse_duration("15m"), Some(Duration::from_secs(900)));
+        assert_eq!(parse_duration("2h"), Some(Duration::from_secs(7200)));
+        assert_eq!(parse_duration("1w"), Some(Duration::from_secs(604800)));
+        assert_eq!(parse_duration("2y"), None);
+        assert_eq!(parse_duration("h"), None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_parse_owner() {
+        assert_eq!(FileFilter::owner("root"), Ok(FileFilter::Owner(0)));
+        assert_eq!(FileFilter::group("root"), Ok(FileFilter::Group(0)));
+        assert_eq!(FileFilter::owner("1000"), Ok(FileFilter::Owner(1000)));
+        assert!(FileFilter::owner("no-such-user-here").is_err());
+        assert!(FileFilter::group("bad\0name").is_err());
+    }
+}
@@ -0,0 +1,78 @@
+use std::fmt;
+use std::time::Duration;
+
+/// Counters collected while walking a tree, `DirNav` fills in the traversal
+/// counts and the `DirEvent` app adds what it knows through `add_stats`
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct TraversalStats {
+    /// Directories handed to `do_dir`
+    pub dirs_visited: usize,
+    /// Directories not looked into, skipped by `do_dir` or on another
+    /// filesystem
+    pub dirs_pruned: usize,
+    /// Regular files found in visited directories, apart from ignored ones
+    pub files_seen: usize,
+    /// Files the app writes itself, never looked at or handed to the app
+    pub files_ignored: usize,
+    /// Files whose extension matched the patterns (all when no patterns)
+    pub files_matched: usize,
+    /// Matched files rejected by size, time, owner or permission filters
+    pub files_filtered: usize,
+    /// FIFOs, sockets and device files that were skipped
+    pub files_special: usize,
+    /// Files the app could not read as text
+    pub files_binary: usize,
+    /// Files over a size limit that were skipped instead of read
+    pub files_too_big: usize,
+    /// Files the app read and searched
+    pub files_searched: usize,
+    /// Files the app found the search text in
+    pub files_found: usize,
+    /// Unchanged files whose result the app took from a cache or index of
+    /// an earlier run instead of searching them
+    pub files_cached: usize,
+    /// Bytes read by the app
+    pub bytes_read: u64,
+    /// Directories or files that could not be read
+    pub errors: usize,
+    /// Time spent walking
+    pub elapsed: Duration,
+}
+
+impl TraversalStats {
+    /// Total number of files that were seen but never searched
+    pub fn files_skipped(&self) -> usize {
+        self.files_seen - self.files_matched
+            + self.files_ignored
+            + self.files_filtered
+            + self.files_special
+            + self.files_binary
+            + self.files_too_big
+    }
+}
+
+impl fmt::Display for TraversalStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Directories visited : {}", self.dirs_visited)?;
+        writeln!(f, "Directories pruned  : {}", self.dirs_pruned)?;
+        writeln!(f, "Files seen          : {}", self.files_seen)?;
+        writeln!(f, "Files matched       : {}", self.files_matched)?;
+        writeln!(f, "Files searched      : {}", self.files_searched)?;
+        writeln!(f, "Files with text     : {}", self.files_found)?;
+        writeln!(f, "Files from cache    : {}", self.files_cached)?;
+        writeln!(
+            f,
+            "Files skipped       : {} (pattern {}, ignored {}, filtered {}, special {}, binary {}, too big {})",
+            self.files_skipped(),
+            self.files_seen - self.files_matched,
+            self.files_ignored,
+            self.files_filtered,
+            self.files_special,
+            self.files_binary,
+            self.files_too_big
+        )?;
+        writeln!(f, "Bytes read          : {}", self.bytes_read)?;
+        writeln!(f, "Errors              : {}", self.errors)?;
+        write!(f, "Elapsed             : {:.3?}", self.elapsed)
+    }
+}
@@ -0,0 +1,240 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io::Read;
+
+/// Built in types, name followed by globs and shebang interpreters
+const BUILTIN_TYPES: &[(&str, &[&str], &[&str])] = &[
+    ("c", &["*.c", "*.h"], &[]),
+    ("cmake", &["CMakeLists.txt", "*.cmake"], &[]),
+    (
+        "cpp",
+        &["*.cpp", "*.cc", "*.cxx", "*.hpp", "*.hh", "*.hxx", "*.h"],
+        &[],
+    ),
+    ("css", &["*.css", "*.scss"], &[]),
+    (
+        "docker",
+        &["Dockerfile", "Dockerfile.*", "*.dockerfile"],
+        &[],
+    ),
+    ("go", &["*.go"], &[]),
+    ("html", &["*.html", "*.htm"], &[]),
+    ("java", &["*.java"], &[]),
+    ("js", &["*.js", "*.mjs", "*.cjs", "*.jsx"], &["node"]),
+    ("json", &["*.json"], &[]),
+    ("lua", &["*.lua"], &["lua"]),
+    (
+        "make",
+        &["Makefile", "makefile", "GNUmakefile", "*.mk"],
+        &["make"],
+    ),
+    ("markdown", &["*.md", "*.markdown"], &[]),
+    ("perl", &["*.pl", "*.pm"], &["perl"]),
+    ("python", &["*.py", "*.pyi"], &["python"]),
+    ("ruby", &["*.rb", "Gemfile", "Rakefile"], &["ruby"]),
+    ("rust", &["*.rs"], &[]),
+    (
+        "sh",
+        &["*.sh", "*.bash", "*.zsh"],
+        &["sh", "bash", "zsh", "dash", "ksh"],
+    ),
+    ("toml", &["*.toml", "Cargo.lock"], &[]),
+    ("ts", &["*.ts", "*.tsx"], &["ts-node", "deno"]),
+    ("txt", &["*.txt"], &[]),
+    ("yaml", &["*.yaml", "*.yml"], &[]),
+];
+
+/// Globs and shebang interpreters making up one named file type
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FileType {
+    /// Globs matched against the file name, e.g. `*.rs` or `Makefile`
+    pub globs: Vec<String>,
+    /// Interpreters in `#!` lines of extensionless scripts, e.g. `python`
+    pub interpreters: Vec<String>,
+}
+
+impl FileType {
+    /// Check the file name against the globs of this type
+    pub fn matches_name(&self, name: &str) -> bool {
+        self.globs.iter().any(|g| glob_match(g, name))
+    }
+}
+
+/// Named file types such as `rust` or `markdown` mapped to globs
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TypeRegistry {
+    types: BTreeMap<String, FileType>,
+}
+
+impl TypeRegistry {
+    /// Registry with all built in types
+    pub fn new() -> Self {
+        let types = BUILTIN_TYPES
+            .iter()
+            .map(|(name, globs, interpreters)| {
+                let file_type = FileType {
+                    globs: globs.iter().map(|g| g.to_string()).collect(),
+                    interpreters: interpreters.iter().map(|i| i.to_string()).collect(),
+                };
+                (name.to_string(), file_type)
+            })
+            .collect();
+
+        Self { types }
+    }
+
+    /// Add a user defined type from `name:glob`, globs are added to the
+    /// type if it already exists
+    pub fn add_definition(&mut self, spec: &str) -> Result<(), String> {
+        let (name, glob) = match spec.split_once(':') {
+            Some((name, glob)) if !name.is_empty() && !glob.is_empty() => (name, glob),
+            _ => {
+                return Err(format!(
+                    "invalid type definition `{}`, expected `name:glob`",
+                    spec
+                ))
+            }
+        };
+
+        self.types
+            .entry(name.to_string())
+            .or_default()
+            .globs
+            .push(glob.to_string());
+
+        Ok(())
+    }
+
+    /// Look up a type by name
+    pub fn get(&self, name: &str) -> Result<&FileType, String> {
+        self.types.get(name).ok_or_else(|| {
+            format!(
+                "unknown file type `{}`, use `--type-list true` to list known types",
+                name
+            )
+        })
+    }
+
+    /// Names of all known types in sorted order
+    pub fn names(&self) -> impl Iterator<Item = &String> {
+        self.types.keys()
+    }
+}
+
+impl Default for TypeRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Display for TypeRegistry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (name, file_type) in &self.types {
+            write!(f, "{}: {}", name, file_type.globs.join(", "))?;
+            if !file_type.interpreters.is_empty() {
+                write!(f, " (#! {})", file_type.interpreters.join(", "))?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+/// Match a file name against a glob supporting `*` and `?`
+pub fn glob_match(glob: &str, name: &str) -> bool {
+    let glob: Vec<char> = glob.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+
+    let (mut g, mut n) = (0, 0);
+    // Position of the last `*` in glob and the name position it matched up to
+    let mut star: Option<(usize, usize)> = None;
+
+    while n < name.len() {
+        if g < glob.len() && (glob[g] == '?' || glob[g] == name[n]) {
+            g += 1;
+            n += 1;
+        } else if g < glob.len() && glob[g] == '*' {
+            star = Some((g, n));
+            g += 1;
+        } else if let Some((star_g, star_n)) = star {
+            // let the last `*` swallow one more character
+            g = star_g + 1;
+            n = star_n + 1;
+            star = Some((star_g, n));
+        } else {
+            return false;
+        }
+    }
+
+    glob[g..].iter().all(|&c| c == '*')
+}
+
+/// Interpreter named in the `#!` line of a script, `#!/usr/bin/env python3`
+/// gives `python`, trailing version numbers are dropped
+pub fn shebang_interpreter(mut reader: impl Read) -> Option<String> {
+    let mut buffer = [0u8; 128];
+    let len = reader.read(&mut buffer).ok()?;
+
+    let head = &buffer[..len];
+    if !head.starts_with(b"#!") {
+        return None;
+    }
+
+    let line = String::from_utf8_lossy(&head[2..]);
+    let line = line.lines().next()?;
+
+    let mut words = line.split_whitespace();
+    let mut program = words.next()?.rsplit('/').next()?;
+
+    if program == "env" {
+        program = words.find(|w| !w.starts_with('-'))?;
+    }
+
+    let name = program.trim_end_matches(|c: char| c.is_ascii_digit() || c == '.');
+    Some(name.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match("*.rs", "dir_nav.rs"));
+        assert!(!glob_match("*.rs", "dir_nav.rs.bak"));
+        assert!(glob_match("Makefile", "Makefile"));
+        assert!(!glob_match("Makefile", "Makefile.am"));
+        assert!(glob_match("Dockerfile.*", "Dockerfile.dev"));
+        assert!(glob_match("test_?.txt", "test_1.txt"));
+        assert!(glob_match("*a*b", "xxaxxbxb"));
+        assert!(!glob_match("*a*b", "xxaxxbx"));
+    }
+
+    #[test]
+    fn test_registry() {
+        let mut registry = TypeRegistry::new();
+
+        assert!(registry.get("rust").unwrap().matches_name("main.rs"));
+        assert!(registry.get("make").unwrap().matches_name("Makefile"));
+        assert!(registry.get("proto").is_err());
+
+        registry.add_definition("proto:*.proto").unwrap();
+        assert!(registry.get("proto").unwrap().matches_name("a.proto"));
+        assert!(registry.add_definition("proto").is_err());
+
+        assert!(registry.to_string().contains("proto: *.proto"));
+    }
+
+    #[test]
+    fn test_shebang_interpreter() {
+        let interpreter = |s: &str| shebang_interpreter(s.as_bytes());
+
+        assert_eq!(
+            interpreter("#!/usr/bin/env python3\n"),
+            Some("python".into())
+        );
+        assert_eq!(interpreter("#!/usr/bin/env -S node\n"), Some("node".into()));
+        assert_eq!(interpreter("#!/bin/bash -e\nls"), Some("bash".into()));
+        assert_eq!(interpreter("echo hi"), None);
+    }
+}
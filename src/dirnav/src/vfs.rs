@@ -0,0 +1,392 @@
+use crate::SpecialFile;
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::fs;
+use std::io::{self, Cursor, Error, ErrorKind, Read, Seek, SeekFrom};
+use std::path::{Component, Path, PathBuf};
+use std::time::SystemTime;
+
+/// Kind of an entry as reported by a `FileSystem`, symlinks are followed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EntryKind {
+    File,
+    Dir,
+    Special(SpecialFile),
+}
+
+/// Metadata of an entry, the subset `DirNav` and its filters need
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EntryMetadata {
+    pub kind: EntryKind,
+    /// Size in bytes
+    pub len: u64,
+    /// Modification time
+    pub modified: Option<SystemTime>,
+    /// Status change time
+    pub changed: Option<SystemTime>,
+    /// Numeric user id of the owner
+    pub uid: Option<u32>,
+    /// Numeric group id of the owner
+    pub gid: Option<u32>,
+    /// Unix permission bits
+    pub mode: Option<u32>,
+    /// ID of the device (filesystem) holding the entry
+    pub device: Option<u64>,
+    /// Inode number, changes when a file is replaced e.g. by log rotation
+    pub inode: Option<u64>,
+}
+
+impl EntryMetadata {
+    /// Metadata with only kind and size set
+    pub fn new(kind: EntryKind, len: u64) -> Self {
+        Self {
+            kind,
+            len,
+            modified: None,
+            changed: None,
+            uid: None,
+            gid: None,
+            mode: None,
+            device: None,
+            inode: None,
+        }
+    }
+
+    pub fn is_dir(&self) -> bool {
+        self.kind == EntryKind::Dir
+    }
+
+    pub fn is_file(&self) -> bool {
+        self.kind == EntryKind::File
+    }
+}
+
+/// Storage `DirNav` walks and `DirEvent` apps read files from
+pub trait FileSystem: Send + Sync {
+    /// Names of the entries of a directory, in no particular order
+    fn read_dir(&self, dir: &Path) -> io::Result<Vec<OsString>>;
+
+    /// Metadata of an entry, following symlinks
+    fn metadata(&self, path: &Path) -> io::Result<EntryMetadata>;
+
+    /// Open a file for reading
+    fn open(&self, path: &Path) -> io::Result<Box<dyn Read + Send>>;
+
+    /// Open a file for reading from `offset` on, the default reads and
+    /// drops the bytes before it
+    fn open_at(&self, path: &Path, offset: u64) -> io::Result<Box<dyn Read + Send>> {
+        let mut reader = self.open(path)?;
+        io::copy(&mut reader.by_ref().take(offset), &mut io::sink())?;
+        Ok(reader)
+    }
+}
+
+/// `FileSystem` backed by `std::fs`
+#[derive(Debug, Clone, Copy, Default)]
+pub struct StdFileSystem;
+
+impl FileSystem for StdFileSystem {
+    fn read_dir(&self, dir: &Path) -> io::Result<Vec<OsString>> {
+        fs::read_dir(dir)?
+            .map(|entry| entry.map(|e| e.file_name()))
+            .collect()
+    }
+
+    fn metadata(&self, path: &Path) -> io::Result<EntryMetadata> {
+        let metadata = fs::metadata(path)?;
+
+        let kind = if metadata.is_dir() {
+            EntryKind::Dir
+        } else {
+            match special_kind(&metadata) {
+                Some(special) => EntryKind::Special(special),
+                None => EntryKind::File,
+            }
+        };
+
+        let mut entry = EntryMetadata::new(kind, metadata.len());
+        entry.modified = metadata.modified().ok();
+        entry.changed = entry.modified;
+        add_unix_metadata(&metadata, &mut entry);
+
+        Ok(entry)
+    }
+
+    fn open(&self, path: &Path) -> io::Result<Box<dyn Read + Send>> {
+        Ok(Box::new(fs::File::open(path)?))
+    }
+
+    fn open_at(&self, path: &Path, offset: u64) -> io::Result<Box<dyn Read + Send>> {
+        let mut file = fs::File::open(path)?;
+        file.seek(SeekFrom::Start(offset))?;
+        Ok(Box::new(file))
+    }
+}
+
+#[cfg(unix)]
+fn special_kind(metadata: &fs::Metadata) -> Option<SpecialFile> {
+    use std::os::unix::fs::FileTypeExt;
+
+    let file_type = metadata.file_type();
+    if file_type.is_fifo() {
+        Some(SpecialFile::Fifo)
+    } else if file_type.is_socket() {
+        Some(SpecialFile::Socket)
+    } else if file_type.is_block_device() {
+        Some(SpecialFile::BlockDevice)
+    } else if file_type.is_char_device() {
+        Some(SpecialFile::CharDevice)
+    } else {
+        None
+    }
+}
+
+/// Only regular files and directories exist
+#[cfg(not(unix))]
+fn special_kind(_metadata: &fs::Metadata) -> Option<SpecialFile> {
+    None
+}
+
+#[cfg(unix)]
+fn add_unix_metadata(metadata: &fs::Metadata, entry: &mut EntryMetadata) {
+    use std::os::unix::fs::MetadataExt;
+    use std::time::Duration;
+
+    let secs = Duration::from_secs(metadata.ctime().max(0) as u64);
+    let nanos = Duration::from_nanos(metadata.ctime_nsec().max(0) as u64);
+    entry.changed = SystemTime::UNIX_EPOCH.checked_add(secs + nanos);
+    entry.uid = Some(metadata.uid());
+    entry.gid = Some(metadata.gid());
+    entry.mode = Some(metadata.mode());
+    entry.device = Some(metadata.dev());
+    entry.inode = Some(metadata.ino());
+}
+
+#[cfg(not(unix))]
+fn add_unix_metadata(_metadata: &fs::Metadata, _entry: &mut EntryMetadata) {}
+
+/// Entry of a `MemoryFileSystem`
+#[derive(Debug, Clone)]
+struct MemoryEntry {
+    metadata: EntryMetadata,
+    contents: Vec<u8>,
+}
+
+/// `FileSystem` holding a synthetic tree in memory, `.` components are
+/// ignored so `./a/b` and `a/b` name the same entry
+#[derive(Debug, Clone)]
+pub struct MemoryFileSystem {
+    entries: BTreeMap<PathBuf, MemoryEntry>,
+}
+
+impl MemoryFileSystem {
+    /// Empty filesystem holding only the root directory
+    pub fn new() -> Self {
+        let mut entries = BTreeMap::new();
+        entries.insert(
+            PathBuf::new(),
+            MemoryEntry {
+                metadata: EntryMetadata::new(EntryKind::Dir, 0),
+                contents: Vec::new(),
+            },
+        );
+
+        Self { entries }
+    }
+
+    /// Add a directory, creating missing parents
+    pub fn add_dir(&mut self, path: &Path) {
+        self.insert(path, EntryMetadata::new(EntryKind::Dir, 0), Vec::new());
+    }
+
+    /// Add a file with contents, creating missing parents
+    pub fn add_file(&mut self, path: &Path, contents: &[u8]) {
+        let mut metadata = EntryMetadata::new(EntryKind::File, contents.len() as u64);
+        metadata.modified = Some(SystemTime::now());
+        metadata.changed = metadata.modified;
+        metadata.mode = Some(0o644);
+        self.insert(path, metadata, contents.to_vec());
+    }
+
+    /// Add a FIFO, socket or device file, creating missing parents
+    pub fn add_special(&mut self, path: &Path, kind: SpecialFile) {
+        self.insert(
+            path,
+            EntryMetadata::new(EntryKind::Special(kind), 0),
+            Vec::new(),
+        );
+    }
+
+    /// Change the metadata of an existing entry, e.g. its time or mode
+    pub fn set_metadata(&mut self, path: &Path, metadata: EntryMetadata) -> io::Result<()> {
+        match self.entries.get_mut(&normalize(path)) {
+            Some(entry) => {
+                entry.metadata = metadata;
+                Ok(())
+            }
+            None => Err(not_found(path)),
+        }
+    }
+
+    /// Remove an entry and everything below it
+    pub fn remove(&mut self, path: &Path) {
+        let path = normalize(path);
+        self.entries.retain(|p, _| !p.starts_with(&path));
+    }
+
+    fn insert(&mut self, path: &Path, metadata: EntryMetadata, contents: Vec<u8>) {
+        let path = normalize(path);
+
+        for parent in path.ancestors().skip(1) {
+            self.entries
+                .entry(parent.to_path_buf())
+                .or_insert_with(|| MemoryEntry {
+                    metadata: EntryMetadata::new(EntryKind::Dir, 0),
+                    contents: Vec::new(),
+                });
+        }
+
+        self.entries
+            .insert(path, MemoryEntry { metadata, contents });
+    }
+
+    fn get(&self, path: &Path) -> io::Result<&MemoryEntry> {
+        self.entries
+            .get(&normalize(path))
+            .ok_or_else(|| not_found(path))
+    }
+}
+
+impl Default for MemoryFileSystem {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FileSystem for MemoryFileSystem {
+    fn read_dir(&self, dir: &Path) -> io::Result<Vec<OsString>> {
+        if !self.get(dir)?.metadata.is_dir() {
+            return Err(Error::other("Not a directory"));
+        }
+
+        let dir = normalize(dir);
+        Ok(self
+            .entries
+            .keys()
+            .filter(|p| p.parent() == Some(dir.as_path()) && !p.as_os_str().is_empty())
+            .filter_map(|p| p.file_name().map(|n| n.to_os_string()))
+            .collect())
+    }
+
+    fn metadata(&self, path: &Path) -> io::Result<EntryMetadata> {
+        Ok(self.get(path)?.metadata.clone())
+    }
+
+    fn open(&self, path: &Path) -> io::Result<Box<dyn Read + Send>> {
+        let entry = self.get(path)?;
+        if entry.metadata.is_dir() {
+            return Err(Error::other("Is a directory"));
+        }
+
+        Ok(Box::new(Cursor::new(entry.contents.clone())))
+    }
+}
+
+/// Drop `.` and root components so equivalent paths map to the same key
+fn normalize(path: &Path) -> PathBuf {
+    path.components()
+        .filter(|c| matches!(c, Component::Normal(_) | Component::ParentDir))
+        .collect()
+}
+
+fn not_found(path: &Path) -> Error {
+    Error::new(
+        ErrorKind::NotFound,
+        format!("{} not found", path.to_string_lossy()),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_memory_file_system() {
+        let mut fs = MemoryFileSystem::new();
+        fs.add_file(Path::new("./a/b/file.txt"), b"hello");
+        fs.add_special(Path::new("a/pipe"), SpecialFile::Fifo);
+
+        let mut names = fs.read_dir(Path::new("a")).unwrap();
+        names.sort();
+        assert_eq!(names, vec![OsString::from("b"), OsString::from("pipe")]);
+        assert_eq!(
+            fs.read_dir(Path::new("./")).unwrap(),
+            vec![OsString::from("a")]
+        );
+
+        assert!(fs.metadata(Path::new("a/b")).unwrap().is_dir());
+        assert_eq!(fs.metadata(Path::new("a/b/file.txt")).unwrap().len, 5);
+        assert!(fs.read_dir(Path::new("a/b/file.txt")).is_err());
+        assert!(fs.metadata(Path::new("missing")).is_err());
+
+        let mut contents = String::new();
+        fs.open(Path::new("a/b/file.txt"))
+            .unwrap()
+            .read_to_string(&mut contents)
+            .unwrap();
+        assert_eq!(contents, "hello");
+
+        contents.clear();
+        fs.open_at(Path::new("a/b/file.txt"), 2)
+            .unwrap()
+            .read_to_string(&mut contents)
+            .unwrap();
+        assert_eq!(contents, "llo");
+
+        fs.remove(Path::new("a/b"));
+        assert!(fs.metadata(Path::new("a/b/file.txt")).is_err());
+    }
+
+    #[test]
+    fn test_std_file_system() {
+        let fs = StdFileSystem;
+
+        assert!(fs.metadata(Path::new("./src")).unwrap().is_dir());
+        assert!(fs.metadata(Path::new("./src/vfs.rs")).unwrap().is_file());
+        assert!(fs
+            .read_dir(Path::new("./src"))
+            .unwrap()
+            .contains(&OsString::from("vfs.rs")));
+
+        let mut contents = String::new();
+        fs.open(Path::new("./src/vfs.rs"))
+            .unwrap()
+            .read_to_string(&mut contents)
+            .unwrap();
+        assert!(contents.contains("StdFileSystem"));
+
+        let mut head = [0u8; 3];
+        fs.open_at(Path::new("./src/vfs.rs"), 4)
+            .unwrap()
+            .read_exact(&mut head)
+            .unwrap();
+        assert_eq!(&head, b"cra");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_std_special_files() {
+        let dir = std::env::temp_dir().join(format!("dirnav-fifo-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let pipe = dir.join("pipe");
+        let status = std::process::Command::new("mkfifo").arg(&pipe).status();
+
+        let fifo = StdFileSystem.metadata(&pipe).map(|m| m.kind);
+        let file = StdFileSystem.metadata(&dir).map(|m| m.kind);
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert!(status.unwrap().success());
+        assert_eq!(fifo.unwrap(), EntryKind::Special(SpecialFile::Fifo));
+        assert_eq!(file.unwrap(), EntryKind::Dir);
+    }
+}
@@ -43,6 +43,24 @@ impl SearchEvent for Display {
             println!("{}", self.current_file.to_string_lossy());
         }
     }
+
+    fn set_line(&mut self, result: (&std::path::Path, usize, &str)) {
+        // streams without a name such as stdin only show the line number
+        if result.0.as_os_str().is_empty() {
+            println!("{}:{}", result.1, result.2);
+        } else {
+            println!("{}:{}:{}", result.0.to_string_lossy(), result.1, result.2);
+        }
+
+        // flush every line so matches show up live in a pipeline
+        let _ = std::io::Write::flush(&mut std::io::stdout());
+    }
+
+    fn set_change(&mut self, result: (&std::path::Path, bool)) {
+        let sign = if result.1 { '+' } else { '-' };
+        println!("{} {}", sign, result.0.to_string_lossy());
+        let _ = std::io::Write::flush(&mut std::io::stdout());
+    }
 }
 
 impl Default for Display {
@@ -1,99 +1,624 @@
-use cli_parser::CliParser;
-use dir_nav::DirNav;
+use cli_parser::{CliError, CliParser, OptionSpec, Source, ValueType};
+use dir_nav::{DirEvent, DirNav, FileFilter, TypeRegistry};
 use display::Display;
-use text_search::TextSearch;
+use std::ffi::{OsStr, OsString};
+use std::io::{BufRead, IsTerminal, Read};
+use std::path::{Component, Path, PathBuf};
+use std::time::{Duration, Instant};
+use text_index::{IndexBuilder, TrigramIndex, DEFAULT_INDEX_FILE};
+use text_search::{ResultCache, TextSearch, DEFAULT_CACHE_FILE};
+
+/// Time between two checks for changes in follow and watch mode
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Time between two walks looking for new files in follow mode, followed
+/// files are polled every `POLL_INTERVAL` without walking
+const RESCAN_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Config file of a project, the closest one to the current directory
+const PROJECT_CONFIG_FILE: &str = ".textfinder.toml";
+
+/// Prefix of the environment variables setting options
+const ENV_PREFIX: &str = "TEXTFINDER";
+
+/// End of the help describing the config files and the part of TOML they
+/// are read with
+const CONFIG_HELP: &str = "
+Options are also read from ~/.config/textfinder/config.toml, the closest
+.textfinder.toml and TEXTFINDER_<NAME> variables. Config files take
+top-level `name = value` lines with the long option names, values are
+strings, integers, booleans or arrays of them. Tables, floats, dates and
+nested arrays are not supported.
+";
+
+/// What to do after parsing the command line
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Parsed {
+    /// Search with the parsed options
+    Search,
+    /// Print the text, e.g. the help, and exit successfully
+    Print(String),
+    /// Print the usage error and exit with status 2
+    UsageError(String),
+}
+
+/// Where options are read from besides the command line
+#[derive(Debug, Clone, Default)]
+pub struct ConfigSources {
+    /// Config file of the user
+    pub user_config: Option<PathBuf>,
+    /// Directory the closest `.textfinder.toml` is looked for from
+    pub project_dir: Option<PathBuf>,
+    /// Environment variables, only the `TEXTFINDER_*` ones are used
+    pub vars: Vec<(OsString, OsString)>,
+}
+
+impl ConfigSources {
+    /// `~/.config/textfinder/config.toml`, the current directory and the
+    /// environment of the process
+    pub fn from_env() -> Self {
+        ConfigSources {
+            user_config: cli_parser::user_config_path("textfinder"),
+            project_dir: std::env::current_dir().ok(),
+            vars: std::env::vars_os().collect(),
+        }
+    }
+}
 
 pub struct Executive {
     cli_parser: CliParser,
     dir_nav: DirNav<TextSearch<Display>>,
+    read_stdin: bool,
+    config: ConfigSources,
 }
 
 impl Executive {
     pub fn new() -> Executive {
+        let mut cli_parser = CliParser::new();
+        cli_parser.set_program_name("textfinder");
+        for spec in options() {
+            cli_parser.add_option(spec);
+        }
+
         Executive {
-            cli_parser: CliParser::new(),
+            cli_parser,
             dir_nav: DirNav::<TextSearch<Display>>::new(),
+            read_stdin: false,
+            config: ConfigSources::from_env(),
         }
     }
 
-    pub fn parse_cla(&mut self, args: &[String]) {
-        self.cli_parser.set_params(args);
-        // Function parse_params implicitly validates the Command Line Arguments
-        self.cli_parser.parse_params();
+    /// Read options from other config files and variables than the ones of
+    /// the user and the process
+    pub fn set_config_sources(&mut self, config: ConfigSources) {
+        self.config = config;
+    }
 
-        // Check if --help is passed
-        if args.iter().any(|i| i == "--help") {
-            std::panic!(
-                "
-                Usage:
-                --path `path/to/target/dir`, default `./`
-                --pattern `search/file/patterns`, default `empty`
-                --text `text to search`, default `\"\"` (lists all files)
-                --recurse `search recursively in sub directories`, default `false`
-                "
-            );
+    pub fn parse_cla<S: AsRef<OsStr>>(&mut self, args: &[S]) -> Parsed {
+        // Function parse_params validates the Command Line Arguments against
+        // the declared options and fills in the others from the config files,
+        // the environment and their defaults
+        if let Err(e) = self.cli_parser.set_params_os(args) {
+            return Parsed::UsageError(e.to_string());
+        }
+
+        // a broken config file or variable must not hide the help or the
+        // config meant to find what is wrong, they are reported after
+        let config_errors = self.load_config();
+
+        if let Err(e) = self.cli_parser.parse_params() {
+            return Parsed::UsageError(e.to_string());
+        }
+
+        if self.cli_parser.is_help_requested() {
+            return Parsed::Print(self.cli_parser.help() + CONFIG_HELP);
+        }
+
+        if self.cli_parser.get_bool("show-config") {
+            let mut config = self.cli_parser.effective_config();
+            for e in &config_errors {
+                config.push_str(&format!("# error: {}\n", e));
+            }
+            return Parsed::Print(config);
+        }
+
+        if let Some(e) = config_errors.first() {
+            return Parsed::UsageError(e.to_string());
+        }
+
+        // an empty argument list is not a command line and never reads stdin
+        let piped = !args.is_empty() && !std::io::stdin().is_terminal();
+        self.read_stdin = self.reads_stdin(piped);
+
+        if let Err(e) = self.check_combinations() {
+            return Parsed::UsageError(e);
+        }
+
+        Parsed::Search
+    }
+
+    /// Check the options that cannot be used together, or need a value
+    /// the parser does not know about
+    fn check_combinations(&self) -> Result<(), String> {
+        let paths = self.cli_parser.get_strings("path");
+        let num_texts = self.cli_parser.get_strings("text").len();
+
+        if self.read_stdin && paths.len() > 1 {
+            return Err("path `-` (stdin) cannot be combined with other paths".to_string());
+        }
+
+        if self.read_stdin && self.cli_parser.get_string("files-from").is_some() {
+            return Err("path `-` (stdin) cannot be used with `files-from`".to_string());
+        }
+
+        if self.read_stdin && num_texts > 1 {
+            return Err("searching stdin takes a single `text`".to_string());
+        }
+
+        if self.is_following() && num_texts > 1 {
+            return Err("`follow` takes a single `text`".to_string());
         }
 
-        // Now set the default values for empty values
-        if self.cli_parser.get_valuesvec_for_key("path").is_none() {
-            self.cli_parser.add_params_key_value("path", "./");
+        if self.is_watching() && num_texts > 1 {
+            return Err("`watch` takes a single `text`".to_string());
         }
 
-        if self.cli_parser.get_valuesvec_for_key("pattern").is_none() {
-            // pattern is empty by default, search on all files
+        // a pass stopped by the limit would leave the hits of the files it
+        // did not get to unchecked
+        if self.is_watching() && self.cli_parser.get_integer("limit").is_some() {
+            return Err("`limit` cannot be used with `watch`".to_string());
         }
 
-        if self.cli_parser.get_valuesvec_for_key("recurse").is_none() {
-            self.cli_parser.add_params_key_value("recurse", "false");
+        if self.is_watching() && self.is_following() {
+            return Err("`watch` and `follow` cannot be used together".to_string());
         }
 
+        if self.is_caching() && (self.is_following() || self.is_watching()) {
+            return Err("`cache` cannot be used with `follow` or `watch`".to_string());
+        }
+
+        self.type_registry()?;
+        self.file_filters()?;
+
+        Ok(())
+    }
+
+    /// `cmd | textfinder ERROR` and `cmd | textfinder ERROR -` search the
+    /// piped stream. A path or file list on the command line is searched
+    /// instead, one from a config file does not count as piping is decided
+    /// per command.
+    fn reads_stdin(&self, piped: bool) -> bool {
+        let on_command_line =
+            |key: &str| self.cli_parser.get_source(key) == Some(&Source::CommandLine);
+
         if self
             .cli_parser
-            .get_valuesvec_for_key("recurse")
-            .unwrap()
-            .len()
-            > 1
+            .get_strings("path")
+            .iter()
+            .any(|path| path == "-")
         {
-            std::panic!(
-                "
-                `recurse` cannot take more than one argument,
-                valid value is either `true` or `false`
-                "
-            );
+            return true;
         }
 
-        if self.cli_parser.get_valuesvec_for_key("recurse").unwrap()[0] != "true"
-            && self.cli_parser.get_valuesvec_for_key("recurse").unwrap()[0] != "false"
-        {
-            std::panic!("valid value for `recurse` is either `true` or `false`");
+        piped && !on_command_line("path") && !on_command_line("files-from")
+    }
+
+    /// Options from the user's config file, the closest `.textfinder.toml`
+    /// and `TEXTFINDER_*` variables, in that order of precedence below the
+    /// command line. A source with an error is left out and the others are
+    /// still read.
+    fn load_config(&mut self) -> Vec<CliError> {
+        let mut errors = Vec::<CliError>::new();
+
+        if let Some(path) = &self.config.user_config {
+            if path.is_file() {
+                errors.extend(self.cli_parser.add_user_config(path).err());
+            }
         }
 
-        if self.cli_parser.get_valuesvec_for_key("text").is_none() {
-            self.cli_parser.add_params_key_value("text", "");
+        let project = self
+            .config
+            .project_dir
+            .as_ref()
+            .and_then(|dir| cli_parser::find_project_config(dir, PROJECT_CONFIG_FILE));
+        if let Some(path) = project {
+            errors.extend(self.cli_parser.add_project_config(&path).err());
         }
+
+        let vars = self.config.vars.clone();
+        errors.extend(self.cli_parser.add_vars(ENV_PREFIX, vars).err());
+        errors
     }
 
     pub fn initialize_dir_nav(&mut self) {
-        let patterns = self.cli_parser.get_valuesvec_for_key("pattern");
-        for pattern in patterns.iter().flat_map(|v| v.iter()) {
-            self.dir_nav.add_patterns(std::path::Path::new(pattern));
+        let mut dir_nav = std::mem::take(&mut self.dir_nav);
+        self.configure_dir_nav(&mut dir_nav);
+        self.dir_nav = dir_nav;
+
+        if let Some(limit) = self.cli_parser.get_integer("limit") {
+            self.dir_nav.get_app().set_max_found(limit);
+        }
+
+        if !self.cli_parser.get_bool("decompress") {
+            self.dir_nav.get_app().set_decompress(false);
+        }
+
+        if self.is_following() {
+            self.dir_nav.get_app().set_follow();
+        }
+
+        if self.is_watching() {
+            self.dir_nav.get_app().set_watch();
+        }
+
+        if self.is_caching() {
+            let cache = match ResultCache::read_from(&self.cache_path()) {
+                Ok(cache) => cache,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => ResultCache::new(),
+                Err(e) => {
+                    eprintln!(
+                        "Cannot use cache {} ({}), searching all files",
+                        self.cache_path().to_string_lossy(),
+                        e
+                    );
+                    ResultCache::new()
+                }
+            };
+            self.dir_nav.get_app().set_cache(cache);
+        }
+    }
+
+    /// Apply the options deciding which files are visited, shared by the
+    /// search and the index builder
+    fn configure_dir_nav<App: DirEvent + Default>(&self, dir_nav: &mut DirNav<App>) {
+        // files textfinder writes are never searched, they may be in the
+        // tree and the cache holds the text searched for
+        dir_nav.add_ignored_file(&self.index_path());
+        dir_nav.add_ignored_file(&self.cache_path());
+
+        for pattern in self.cli_parser.get_strings("pattern") {
+            dir_nav.add_patterns(std::path::Path::new(pattern));
+        }
+
+        if self.cli_parser.get_bool("recurse") {
+            dir_nav.set_recursive_search();
+        }
+
+        let sort = self.cli_parser.get_string("sort").unwrap();
+        dir_nav.set_sort_order(sort.parse().unwrap());
+
+        let traversal = self.cli_parser.get_string("traversal").unwrap();
+        dir_nav.set_traversal_mode(traversal.parse().unwrap());
+
+        for filter in self.file_filters().unwrap() {
+            dir_nav.add_filter(filter);
+        }
+
+        if self.cli_parser.get_bool("one-file-system") {
+            dir_nav.set_one_file_system();
+        }
+
+        if self.cli_parser.get_bool("special") {
+            dir_nav.set_include_special_files();
+        }
+
+        if self.cli_parser.get_bool("archives") {
+            dir_nav.set_search_archives();
+        }
+
+        *dir_nav.get_type_registry() = self.type_registry().unwrap();
+
+        for name in self.cli_parser.get_strings("type") {
+            dir_nav.add_type(name).unwrap();
+        }
+
+        for name in self.cli_parser.get_strings("type-not") {
+            dir_nav.add_type_not(name).unwrap();
+        }
+    }
+
+    /// Build the type registry with user defined types, checking that all
+    /// types passed to `--type` and `--type-not` are known
+    fn type_registry(&self) -> Result<TypeRegistry, String> {
+        let mut registry = TypeRegistry::new();
+
+        let values = |key: &str| self.cli_parser.get_strings(key);
+
+        for spec in values("type-add") {
+            registry.add_definition(spec)?;
         }
+        for name in values("type").iter().chain(values("type-not").iter()) {
+            registry.get(name)?;
+        }
+
+        Ok(registry)
+    }
+
+    /// Build the size, time, owner and permission filters from the arguments
+    fn file_filters(&self) -> Result<Vec<FileFilter>, String> {
+        let mut filters = Vec::<FileFilter>::new();
+
+        let values = |key: &str| self.cli_parser.get_strings(key);
 
-        if self.cli_parser.get_valuesvec_for_key("recurse").unwrap()[0] == "true" {
-            self.dir_nav.set_recursive_search();
+        for spec in values("size") {
+            filters.push(FileFilter::size(spec)?);
+        }
+        for spec in values("newer") {
+            filters.push(FileFilter::modified(spec, true)?);
+        }
+        for spec in values("older") {
+            filters.push(FileFilter::modified(spec, false)?);
+        }
+        for spec in values("changed-newer") {
+            filters.push(FileFilter::changed(spec, true)?);
+        }
+        for spec in values("changed-older") {
+            filters.push(FileFilter::changed(spec, false)?);
         }
+        for spec in values("user") {
+            filters.push(FileFilter::owner(spec)?);
+        }
+        for spec in values("group") {
+            filters.push(FileFilter::group(spec)?);
+        }
+        if self.cli_parser.get_bool("executable") {
+            filters.push(FileFilter::Executable);
+        }
+
+        Ok(filters)
     }
 
-    pub fn start_text_finder(&mut self) {
-        let texts = self.cli_parser.get_valuesvec_for_key("text");
-        let paths = self.cli_parser.get_valuesvec_for_key("path").unwrap();
+    /// Search with the parsed options. A path that cannot be searched is
+    /// reported on stderr and the others are still searched, the error
+    /// returned at the end says how many failed.
+    pub fn start_text_finder(&mut self) -> Result<(), String> {
+        if self.cli_parser.get_bool("type-list") {
+            print!("{}", self.dir_nav.get_type_registry());
+            return Ok(());
+        }
+
+        let update = self.cli_parser.get_bool("update-index");
+        if update || self.cli_parser.get_bool("build-index") {
+            return self.build_index(update);
+        }
+
+        if self.read_stdin {
+            return self
+                .search_stream(std::io::stdin().lock())
+                .map_err(|e| format!("cannot read stdin: {}", e));
+        }
+
+        let texts = self.cli_parser.get_strings("text");
+        let paths = self.cli_parser.get_strings("path");
+        let recurse = self.cli_parser.get_bool("recurse");
+
+        let files = match self.cli_parser.get_string("files-from") {
+            Some(source) => Some(
+                read_file_list(source)
+                    .map_err(|e| format!("cannot read file list `{}`: {}", source, e))?,
+            ),
+            None => None,
+        };
+
+        let index = if files.is_none() && self.cli_parser.get_bool("use-index") {
+            match TrigramIndex::read_from(&self.index_path()) {
+                Ok(index) => {
+                    self.check_index_age(&index);
+                    Some(index)
+                }
+                Err(e) => {
+                    eprintln!(
+                        "Cannot use index {} ({}), searching all files",
+                        self.index_path().to_string_lossy(),
+                        e
+                    );
+                    None
+                }
+            }
+        } else {
+            None
+        };
+
+        let mut failed = Vec::<&String>::new();
 
-        for text in texts.iter().flat_map(|v| v.iter()) {
+        for text in texts {
             self.dir_nav.get_app().set_search_text(text);
+
+            // a file list replaces walking the paths
+            if let Some(files) = &files {
+                self.dir_nav.visit_files(files.iter().cloned());
+                continue;
+            }
+
+            // the index narrows the walk down to the files that may
+            // contain the text, TextSearch still verifies each of them
+            if let Some(index) = &index {
+                let candidates = index
+                    .candidates(text)
+                    .into_iter()
+                    .filter(|f| is_under(f, paths, recurse));
+                self.dir_nav.visit_files(candidates);
+                continue;
+            }
+
             for path in paths {
-                self.dir_nav.visit(std::path::Path::new(path)).unwrap();
+                if let Err(e) = self.dir_nav.visit(std::path::Path::new(path)) {
+                    if !failed.contains(&path) {
+                        eprintln!("error: cannot search `{}`: {}", path, e);
+                        failed.push(path);
+                    }
+                }
+            }
+        }
+
+        let cache_path = self.cache_path();
+        if let Some(cache) = self.dir_nav.get_app().get_cache() {
+            if let Err(e) = cache.write_to(&cache_path) {
+                eprintln!(
+                    "Cannot write cache {} ({})",
+                    cache_path.to_string_lossy(),
+                    e
+                );
+            }
+        }
+
+        for mount in self.dir_nav.get_skipped_mount_points() {
+            eprintln!("Skipped mount point {}", mount.to_string_lossy());
+        }
+
+        if self.dir_nav.get_number_of_special_files() > 0 {
+            eprintln!(
+                "Skipped {} FIFOs, sockets or device files",
+                self.dir_nav.get_number_of_special_files()
+            );
+        }
+
+        if self.cli_parser.get_bool("stats") {
+            println!("===== Summary ===============");
+            println!("{}", self.dir_nav.get_stats());
+        }
+
+        // follow and watch keep going with the paths that can be searched
+        if !failed.is_empty() && !self.is_following() && !self.is_watching() {
+            return Err(format!(
+                "{} of {} paths could not be searched",
+                failed.len(),
+                paths.len()
+            ));
+        }
+
+        if self.is_following() {
+            println!(
+                "Following {} files, press Ctrl-C to stop",
+                self.dir_nav.get_app().get_num_followed()
+            );
+
+            let mut last_walk = Instant::now();
+            loop {
+                std::thread::sleep(POLL_INTERVAL);
+
+                // poll before walking so rotated files continue where they
+                // were left once they are found under their new name
+                self.dir_nav.get_app().poll_followed();
+
+                if files.is_none() && last_walk.elapsed() >= RESCAN_INTERVAL {
+                    last_walk = Instant::now();
+                    for path in paths {
+                        let _ = self.dir_nav.visit(std::path::Path::new(path));
+                    }
+                }
             }
         }
+
+        if self.is_watching() {
+            self.dir_nav.get_app().end_watch_pass();
+            println!("Watching for changes, press Ctrl-C to stop");
+
+            loop {
+                std::thread::sleep(POLL_INTERVAL);
+
+                if let Some(files) = &files {
+                    self.dir_nav.visit_files(files.iter().cloned());
+                } else {
+                    for path in paths {
+                        let _ = self.dir_nav.visit(std::path::Path::new(path));
+                    }
+                }
+
+                self.dir_nav.get_app().end_watch_pass();
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Search a stream line by line instead of walking paths
+    fn search_stream(&mut self, reader: impl BufRead) -> std::io::Result<()> {
+        let text = self.cli_parser.get_string("text").unwrap();
+        let app = self.dir_nav.get_app();
+        app.set_search_text(text);
+        app.search_lines(std::path::Path::new(""), reader)
+    }
+
+    /// Walk the paths like a search would and write a trigram index of the
+    /// files found, an update only reads the files that changed since the
+    /// existing index was written. Nothing is written when a path cannot
+    /// be walked.
+    fn build_index(&self, update: bool) -> Result<(), String> {
+        let index_path = self.index_path();
+
+        let mut dir_nav = DirNav::<IndexBuilder>::new();
+        self.configure_dir_nav(&mut dir_nav);
+
+        if update {
+            match TrigramIndex::read_from(&index_path) {
+                Ok(previous) => dir_nav.get_app().set_previous(previous),
+                Err(e) => eprintln!(
+                    "Cannot update index {} ({}), building it",
+                    index_path.to_string_lossy(),
+                    e
+                ),
+            }
+        }
+
+        for path in self.cli_parser.get_strings("path") {
+            dir_nav
+                .visit(std::path::Path::new(path))
+                .map_err(|e| format!("cannot index `{}`: {}", path, e))?;
+        }
+
+        let app = dir_nav.get_app();
+        let index = app.get_index();
+        index
+            .write_to(&index_path)
+            .map_err(|e| format!("cannot write index {}: {}", index_path.to_string_lossy(), e))?;
+
+        println!(
+            "Indexed {} files into {}, {} unchanged, {} removed",
+            index.len(),
+            index_path.to_string_lossy(),
+            app.get_num_reused(),
+            app.get_num_removed()
+        );
+
+        Ok(())
+    }
+
+    /// Warn when files changed since the index was written, the search
+    /// would miss new files and text added to changed ones
+    fn check_index_age(&self, index: &TrigramIndex) {
+        let written = std::fs::metadata(self.index_path()).and_then(|m| m.modified());
+        if let Ok(written) = written {
+            if index.is_stale(&*self.dir_nav.get_file_system(), written) {
+                eprintln!(
+                    "Index {} is older than the files it covers, run with --update-index",
+                    self.index_path().to_string_lossy()
+                );
+            }
+        }
+    }
+
+    fn index_path(&self) -> PathBuf {
+        self.cli_parser.get_path("index").unwrap().to_path_buf()
+    }
+
+    fn cache_path(&self) -> PathBuf {
+        self.cli_parser
+            .get_path("cache-file")
+            .unwrap()
+            .to_path_buf()
+    }
+
+    fn is_caching(&self) -> bool {
+        self.cli_parser.get_bool("cache")
+    }
+
+    fn is_watching(&self) -> bool {
+        self.cli_parser.get_bool("watch")
+    }
+
+    fn is_following(&self) -> bool {
+        self.cli_parser.get_bool("follow")
     }
 
     pub fn get_valuesvec_for_key(&self, key: &str) -> core::option::Option<&Vec<String>> {
@@ -105,6 +630,177 @@ impl Executive {
     }
 }
 
+/// Options textfinder accepts
+fn options() -> Vec<OptionSpec> {
+    let flag =
+        |name: &str, help: &str| OptionSpec::new(name).value_type(ValueType::Bool).help(help);
+    let filter = |name: &str, help: &str| OptionSpec::new(name).repeatable().help(help);
+
+    vec![
+        OptionSpec::new("text")
+            .short('t')
+            .default_value("")
+            .repeatable()
+            .dedup()
+            .positional()
+            .help("text to search, lists all files when empty"),
+        OptionSpec::new("path")
+            .short('p')
+            .value_type(ValueType::Path)
+            .default_value("./")
+            .repeatable()
+            .dedup()
+            .positional()
+            .help("path/to/target/dir, - or none for piped stdin"),
+        OptionSpec::new("pattern")
+            .repeatable()
+            .help("search/file/patterns, all files when empty"),
+        flag("recurse", "search recursively in sub directories")
+            .short('r')
+            .default_value("false"),
+        OptionSpec::new("sort")
+            .choices(&["none", "name", "size", "mtime"])
+            .default_value("none")
+            .help("order of traversal"),
+        OptionSpec::new("traversal")
+            .choices(&["depth", "breadth"])
+            .default_value("depth")
+            .help("walk sub directories depth or breadth first"),
+        OptionSpec::new("limit")
+            .value_type(ValueType::Integer)
+            .help("stop after this many files containing text"),
+        filter("size", "file size range like +10M or -1k"),
+        filter(
+            "newer",
+            "modified within a duration like 2h or after a reference file",
+        ),
+        filter(
+            "older",
+            "modified before a duration like 2h or a reference file",
+        ),
+        filter("changed-newer", "same as newer for the status change time"),
+        filter("changed-older", "same as older for the status change time"),
+        filter("user", "owner name or id"),
+        filter("group", "group name or id"),
+        flag("executable", "only files with an executable bit"),
+        flag("one-file-system", "do not descend into other filesystems"),
+        flag("special", "also read FIFOs, sockets and device files"),
+        flag("archives", "search inside tar, tar.gz and zip files"),
+        flag(
+            "decompress",
+            "search gz, bz2, xz and zst files decompressed",
+        )
+        .default_value("true"),
+        OptionSpec::new("files-from")
+            .value_type(ValueType::Path)
+            .help("file listing the files to search, newline or NUL separated, - for stdin"),
+        flag(
+            "follow",
+            "keep reporting matching lines appended to the files",
+        )
+        .short('f'),
+        flag(
+            "watch",
+            "re-search changed files and print new (+) and resolved (-) hits",
+        )
+        .short('w'),
+        flag(
+            "build-index",
+            "write a trigram index of the files under path and exit",
+        ),
+        flag(
+            "update-index",
+            "re-index only the files changed since the index was written",
+        ),
+        flag(
+            "use-index",
+            "search only files the index lists as candidates",
+        ),
+        OptionSpec::new("index")
+            .value_type(ValueType::Path)
+            .default_value(DEFAULT_INDEX_FILE)
+            .help("index file"),
+        flag(
+            "cache",
+            "replay results of unchanged files from earlier runs",
+        ),
+        OptionSpec::new("cache-file")
+            .value_type(ValueType::Path)
+            .default_value(DEFAULT_CACHE_FILE)
+            .help("result cache file"),
+        flag("stats", "print a summary of the traversal at the end"),
+        filter("type", "search files of a named type like rust"),
+        filter("type-not", "skip files of a named type"),
+        filter("type-add", "define a type as name:glob, e.g. proto:*.proto"),
+        flag("type-list", "list all known file types and exit"),
+        flag(
+            "show-config",
+            "print the effective options and where each comes from, then exit",
+        ),
+    ]
+}
+
+/// Check whether `file` is one of `paths` or inside one of them, directly
+/// unless `recurse`, the way a walk would find it. `./` is ignored on both
+/// sides so an index built for `.` serves `src`.
+fn is_under(file: &Path, paths: &[String], recurse: bool) -> bool {
+    let file = without_cur_dir(file);
+    paths.iter().any(|path| {
+        let path = without_cur_dir(Path::new(path));
+        if recurse {
+            file.starts_with(path)
+        } else {
+            file == path || file.parent() == Some(path.as_path())
+        }
+    })
+}
+
+/// `path` without its `.` components
+fn without_cur_dir(path: &Path) -> PathBuf {
+    path.components()
+        .filter(|c| !matches!(c, Component::CurDir))
+        .collect()
+}
+
+/// Read a list of files from a file, or from stdin for `-`
+fn read_file_list(source: &str) -> std::io::Result<Vec<PathBuf>> {
+    let mut contents = Vec::new();
+    if source == "-" {
+        std::io::stdin().read_to_end(&mut contents)?;
+    } else {
+        std::fs::File::open(source)?.read_to_end(&mut contents)?;
+    }
+
+    Ok(parse_file_list(&contents))
+}
+
+/// Split a file list on NUL bytes if it has any, as written by
+/// `find -print0` or `git ls-files -z`, otherwise on newlines
+fn parse_file_list(contents: &[u8]) -> Vec<PathBuf> {
+    let separator = if contents.contains(&0) { 0 } else { b'\n' };
+
+    contents
+        .split(|&b| b == separator)
+        .map(|line| match separator {
+            b'\n' => line.strip_suffix(b"\r").unwrap_or(line),
+            _ => line,
+        })
+        .filter(|line| !line.is_empty())
+        .map(path_from_bytes)
+        .collect()
+}
+
+#[cfg(unix)]
+fn path_from_bytes(bytes: &[u8]) -> PathBuf {
+    use std::os::unix::ffi::OsStrExt;
+    PathBuf::from(std::ffi::OsStr::from_bytes(bytes))
+}
+
+#[cfg(not(unix))]
+fn path_from_bytes(bytes: &[u8]) -> PathBuf {
+    PathBuf::from(String::from_utf8_lossy(bytes).into_owned())
+}
+
 impl Default for Executive {
     fn default() -> Self {
         Self::new()
@@ -115,9 +811,17 @@ impl Default for Executive {
 mod tests {
     use super::*;
 
+    /// Executive reading only the command line, not the config files and
+    /// variables of whoever runs the tests
+    fn executive() -> Executive {
+        let mut ex = Executive::new();
+        ex.set_config_sources(ConfigSources::default());
+        ex
+    }
+
     #[test]
     fn test_construction_default() {
-        let mut ex = Executive::new();
+        let mut ex = executive();
         ex.parse_cla(&Vec::<String>::new());
         assert_eq!(
             ex.cli_parser.get_valuesvec_for_key("path").unwrap().len(),
@@ -149,8 +853,9 @@ mod tests {
 
     #[test]
     fn test_construction_custom() {
-        let mut ex = Executive::new();
+        let mut ex = executive();
         let cl_arguments: Vec<String> = vec![
+            "textfinder".to_string(),
             "--path".to_string(),
             "./".to_string(),
             "--pattern".to_string(),
@@ -204,14 +909,343 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_construction_exit() {
+        let mut ex = executive();
+        match ex.parse_cla(&["textfinder", "--help"]) {
+            Parsed::Print(help) => {
+                assert!(help.contains("show-config"));
+                assert!(help.contains("Tables, floats, dates and\nnested arrays are not supported"));
+            }
+            parsed => panic!("expected the help, got {:?}", parsed),
+        }
+
+        let mut ex = executive();
+        match ex.parse_cla(&["textfinder", "--show-config", "--limit", "3"]) {
+            Parsed::Print(config) => assert!(config.contains("limit = 3")),
+            parsed => panic!("expected the config, got {:?}", parsed),
+        }
+
+        let mut ex = executive();
+        assert!(matches!(
+            ex.parse_cla(&["textfinder", "--no-such-option"]),
+            Parsed::UsageError(_)
+        ));
+
+        let mut ex = executive();
+        assert_eq!(ex.parse_cla(&["textfinder", "ERROR"]), Parsed::Search);
+    }
+
+    #[test]
+    fn test_config_sources() {
+        let dir = std::env::temp_dir().join(format!("executive-config-{}", std::process::id()));
+        let project = dir.join("project").join("src");
+        std::fs::create_dir_all(&project).unwrap();
+        let user = dir.join("config.toml");
+        std::fs::write(&user, "limit = 1\nsort = \"name\"\npattern = [\"a\"]\n").unwrap();
+        std::fs::write(
+            dir.join("project").join(PROJECT_CONFIG_FILE),
+            "limit = 2\npattern = [\"b\"]\n",
+        )
+        .unwrap();
+
+        let sources = |vars: &[(&str, &str)]| ConfigSources {
+            user_config: Some(user.clone()),
+            project_dir: Some(project.clone()),
+            vars: vars
+                .iter()
+                .map(|(var, value)| (OsString::from(var), OsString::from(value)))
+                .collect(),
+        };
+
+        // user config, then project config, then environment, then the
+        // command line
+        let mut ex = executive();
+        ex.set_config_sources(sources(&[("TEXTFINDER_LIMIT", "3")]));
+        assert_eq!(ex.parse_cla(&["textfinder", "ERROR"]), Parsed::Search);
+        assert_eq!(ex.cli_parser.get_string("sort"), Some("name"));
+        assert_eq!(ex.cli_parser.get_strings("pattern"), ["b"]);
+        assert_eq!(ex.cli_parser.get_integer("limit"), Some(3));
+
+        let mut ex = executive();
+        ex.set_config_sources(sources(&[("TEXTFINDER_LIMIT", "3")]));
+        ex.parse_cla(&["textfinder", "ERROR", "--limit", "4"]);
+        assert_eq!(ex.cli_parser.get_integer("limit"), Some(4));
+
+        // a bad variable fails the search but not the help or the config
+        let bad = sources(&[("TEXTFINDER_LIMIT", "x")]);
+        let mut ex = executive();
+        ex.set_config_sources(bad.clone());
+        let search = ex.parse_cla(&["textfinder", "ERROR"]);
+        let mut ex = executive();
+        ex.set_config_sources(bad.clone());
+        let help = ex.parse_cla(&["textfinder", "--help"]);
+        let mut ex = executive();
+        ex.set_config_sources(bad);
+        let config = ex.parse_cla(&["textfinder", "--show-config"]);
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert!(matches!(search, Parsed::UsageError(e) if e.contains("TEXTFINDER_LIMIT")));
+        assert!(matches!(help, Parsed::Print(_)));
+        match config {
+            Parsed::Print(config) => {
+                assert!(config.contains("limit = 2"));
+                assert!(config.contains("# error: ") && config.contains("TEXTFINDER_LIMIT"));
+            }
+            parsed => panic!("expected the config, got {:?}", parsed),
+        }
+    }
+
+    #[test]
+    fn test_construction_invalid_filter() {
+        let mut ex = executive();
+        let cl_arguments: Vec<String> = vec![
+            "textfinder".to_string(),
+            "--size".to_string(),
+            "+10X".to_string(),
+        ];
+
+        assert!(matches!(
+            ex.parse_cla(&cl_arguments),
+            Parsed::UsageError(e) if e.contains("+10X")
+        ));
+    }
+
+    #[test]
+    fn test_construction_watch_limit() {
+        let mut ex = executive();
+        assert_eq!(
+            ex.parse_cla(&["textfinder", "--watch", "--limit", "1"]),
+            Parsed::UsageError("`limit` cannot be used with `watch`".to_string())
+        );
+    }
+
+    #[test]
+    fn test_construction_less_than_filter() {
+        // `-1k` starts with a dash but is the value `--size` needs
+        let mut ex = executive();
+        let cl_arguments: Vec<String> = vec![
+            "textfinder".to_string(),
+            "--path".to_string(),
+            "./".to_string(),
+            "--size".to_string(),
+            "-1k".to_string(),
+        ];
+
+        ex.parse_cla(&cl_arguments);
+        assert_eq!(ex.cli_parser.get_strings("size"), ["-1k"]);
+        assert_eq!(ex.file_filters().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_stdin() {
+        let reads_stdin = |args: &[&str], piped: bool| {
+            let mut ex = executive();
+            ex.parse_cla(args);
+            ex.reads_stdin(piped)
+        };
+
+        // piped stdin is searched unless the command line names paths
+        assert!(reads_stdin(&["textfinder", "ERROR"], true));
+        assert!(!reads_stdin(&["textfinder", "ERROR"], false));
+        assert!(!reads_stdin(&["textfinder", "ERROR", "src"], true));
+        assert!(!reads_stdin(
+            &["textfinder", "ERROR", "--files-from", "list"],
+            true
+        ));
+
+        // `-` reads stdin even from a terminal
+        assert!(reads_stdin(&["textfinder", "ERROR", "-"], false));
+
+        let mut ex = executive();
+        ex.parse_cla(&["textfinder", "ERROR", "-"]);
+        assert!(ex.read_stdin);
+
+        ex.initialize_dir_nav();
+        ex.search_stream(std::io::Cursor::new("ok\nERROR disk full\nok\n"))
+            .unwrap();
+        assert_eq!(ex.dir_nav.get_app().get_num_found(), 1);
+
+        // a stream that fails to read is an error, not a panic
+        struct Broken;
+        impl Read for Broken {
+            fn read(&mut self, _buf: &mut [u8]) -> std::io::Result<usize> {
+                Err(std::io::ErrorKind::BrokenPipe.into())
+            }
+        }
+        let error = ex
+            .search_stream(std::io::BufReader::new(Broken))
+            .unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::BrokenPipe);
+    }
+
     #[test]
     fn test_execution() {
-        let mut ex = Executive::new();
+        let mut ex = executive();
         ex.parse_cla(&Vec::<String>::new());
         ex.initialize_dir_nav();
-        ex.start_text_finder();
+        ex.start_text_finder().unwrap();
 
         // Atleast 1 file is processed no matter where we run from
         assert!(ex.dir_nav.get_number_of_files_processed() > 0);
     }
+
+    #[test]
+    fn test_execution_bad_path() {
+        // the bad path is reported and the other one still searched
+        let mut ex = executive();
+        ex.parse_cla(&["textfinder", "ERROR", "no/such/dir", "./"]);
+        ex.initialize_dir_nav();
+        assert_eq!(
+            ex.start_text_finder(),
+            Err("1 of 2 paths could not be searched".to_string())
+        );
+        assert!(ex.dir_nav.get_number_of_files_processed() > 0);
+
+        let mut ex = executive();
+        ex.parse_cla(&["textfinder", "ERROR", "--files-from", "no/such/list"]);
+        ex.initialize_dir_nav();
+        assert!(ex.start_text_finder().unwrap_err().contains("no/such/list"));
+    }
+
+    #[test]
+    fn test_own_files_not_searched() {
+        let dir = std::env::temp_dir().join(format!("executive-own-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("log.txt"), "ERROR disk full\n").unwrap();
+        let dir_arg = dir.to_string_lossy().into_owned();
+        let index = dir.join(DEFAULT_INDEX_FILE).to_string_lossy().into_owned();
+        let cache = dir.join(DEFAULT_CACHE_FILE).to_string_lossy().into_owned();
+
+        let run = |args: &[&str]| {
+            let mut ex = executive();
+            let mut cla = vec!["textfinder", "ERROR", &dir_arg, "--index", &index];
+            cla.extend(["--cache-file", &cache]);
+            cla.extend(args);
+            assert_eq!(ex.parse_cla(&cla), Parsed::Search);
+            ex.initialize_dir_nav();
+            ex.start_text_finder().unwrap();
+            ex.dir_nav.get_stats()
+        };
+
+        run(&["--build-index"]);
+        run(&["--cache"]);
+        let second = run(&["--cache"]);
+        let indexed = run(&["--use-index"]);
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        // only log.txt, not the index and the cache written next to it
+        assert_eq!(second.files_seen, 1);
+        assert_eq!(second.files_ignored, 2);
+        assert_eq!(indexed.files_seen, 1);
+    }
+
+    #[test]
+    fn test_cache_queries() {
+        let dir = std::env::temp_dir().join(format!("executive-cache-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("log.txt"), "ERROR disk full\n").unwrap();
+        let dir_arg = dir.to_string_lossy().into_owned();
+        let cache = dir.join(DEFAULT_CACHE_FILE).to_string_lossy().into_owned();
+
+        let run = |text: &str| {
+            let mut ex = executive();
+            let cla = [
+                "textfinder",
+                text,
+                &dir_arg,
+                "--cache",
+                "--cache-file",
+                &cache,
+            ];
+            assert_eq!(ex.parse_cla(&cla), Parsed::Search);
+            ex.initialize_dir_nav();
+            ex.start_text_finder().unwrap();
+            ex.dir_nav.get_stats()
+        };
+
+        run("ERROR");
+        run("foo");
+        let again = run("ERROR");
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        // another text in between does not drop the results of the first
+        assert_eq!(again.files_cached, 1);
+        assert_eq!(again.files_searched, 0);
+    }
+
+    #[test]
+    fn test_index_recurse() {
+        let dir = std::env::temp_dir().join(format!("executive-recurse-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("sub")).unwrap();
+        std::fs::write(dir.join("top.txt"), "needle\n").unwrap();
+        std::fs::write(dir.join("sub").join("deep.txt"), "needle\n").unwrap();
+        let dir_arg = dir.to_string_lossy().into_owned();
+        let index = dir.join(DEFAULT_INDEX_FILE).to_string_lossy().into_owned();
+
+        let run = |args: &[&str]| {
+            let mut ex = executive();
+            let mut cla = vec!["textfinder", "needle", &dir_arg, "--index", &index];
+            cla.extend(args);
+            assert_eq!(ex.parse_cla(&cla), Parsed::Search);
+            ex.initialize_dir_nav();
+            ex.start_text_finder().unwrap();
+            ex.dir_nav.get_stats().files_found
+        };
+
+        run(&["-r", "--build-index"]);
+        let walked = run(&[]);
+        let indexed = run(&["--use-index"]);
+        let indexed_recursive = run(&["-r", "--use-index"]);
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        // an index built recursively still only serves top.txt without -r
+        assert_eq!(walked, 1);
+        assert_eq!(indexed, walked);
+        assert_eq!(indexed_recursive, 2);
+    }
+
+    #[test]
+    fn test_is_under() {
+        let paths = |paths: &[&str]| paths.iter().map(|p| p.to_string()).collect::<Vec<_>>();
+
+        assert!(is_under(Path::new("./src/a.rs"), &paths(&["src"]), true));
+        assert!(is_under(Path::new("src/a.rs"), &paths(&["./src/"]), true));
+        assert!(is_under(Path::new("./src/a.rs"), &paths(&["./"]), true));
+        assert!(is_under(Path::new("src/a.rs"), &paths(&["."]), true));
+        assert!(!is_under(Path::new("./srcs/a.rs"), &paths(&["src"]), true));
+        assert!(!is_under(
+            Path::new("./src/a.rs"),
+            &paths(&["doc", "test"]),
+            true
+        ));
+
+        // without recursion only files right in the path
+        assert!(is_under(Path::new("./top.rs"), &paths(&["."]), false));
+        assert!(is_under(Path::new("src/a.rs"), &paths(&["./src"]), false));
+        assert!(is_under(
+            Path::new("src/a.rs"),
+            &paths(&["src/a.rs"]),
+            false
+        ));
+        assert!(!is_under(Path::new("./src/a.rs"), &paths(&["."]), false));
+        assert!(!is_under(
+            Path::new("src/deep/a.rs"),
+            &paths(&["src"]),
+            false
+        ));
+    }
+
+    #[test]
+    fn test_parse_file_list() {
+        assert_eq!(
+            parse_file_list(b"src/a.rs\r\nsrc/b c.rs\n\n"),
+            vec![PathBuf::from("src/a.rs"), PathBuf::from("src/b c.rs")]
+        );
+        assert_eq!(
+            parse_file_list(b"src/a.rs\0with\nnewline.rs\0"),
+            vec![PathBuf::from("src/a.rs"), PathBuf::from("with\nnewline.rs")]
+        );
+        assert!(parse_file_list(b"").is_empty());
+    }
 }
@@ -0,0 +1,35 @@
+use dir_nav::DirNav;
+use std::io;
+use std::path::Path;
+use text_index::*;
+
+fn main() -> io::Result<()> {
+    let mut app = DirNav::<IndexBuilder>::new();
+
+    println!("===== Note ===========");
+
+    println!(
+        "Path used in example app is relative, \
+           it needs to be executed inside the \
+           folder, `textindex`"
+    );
+
+    app.add_patterns(Path::new("rs"));
+    app.add_patterns(Path::new("toml"));
+    app.set_recursive_search();
+
+    println!("===== Build index ===========");
+    app.visit(Path::new("./"))?;
+
+    let index = app.get_app().get_index();
+    println!("Indexed {} files", index.len());
+
+    for text in ["TrigramIndex", "DoesNotExist"] {
+        println!("===== Candidates for `{}` ===========", text);
+        for file in index.candidates(text) {
+            println!("{}", file.to_string_lossy());
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,658 @@
+use bin_io::{
+    path_from_bytes, path_to_bytes, read_bytes, read_u32, read_u64, write_bytes, write_u32,
+    write_u64,
+};
+use dir_nav::{DirAction, DirEvent, EntryMetadata, FileSystem, StdFileSystem, TraversalStats};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fs;
+use std::io::{self, BufReader, BufWriter, Error, ErrorKind, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use text_search::decompress;
+
+/// File name of the index when no other is given
+pub const DEFAULT_INDEX_FILE: &str = ".textfinder-index";
+
+/// Magic bytes at the start of an index file, followed by the format version
+const MAGIC: &[u8; 7] = b"TFIDX\0\0";
+
+/// Version of the index format written by `write_to`
+const VERSION: u8 = 2;
+
+/// Three consecutive bytes of a file
+pub type Trigram = [u8; 3];
+
+/// Set of all trigrams occurring in `contents`
+pub fn trigrams(contents: &[u8]) -> BTreeSet<Trigram> {
+    contents.windows(3).map(|w| [w[0], w[1], w[2]]).collect()
+}
+
+/// 64 bit FNV-1a hash of `contents`, stable across builds so it can be
+/// stored in the index
+pub fn content_hash(contents: &[u8]) -> u64 {
+    contents.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &b| {
+        (hash ^ b as u64).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+/// A file in the index with the state it was indexed in
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IndexedFile {
+    pub path: PathBuf,
+    /// Size in bytes on disk
+    pub len: u64,
+    /// Modification time
+    pub modified: Option<SystemTime>,
+    /// `content_hash` of the (decompressed) contents
+    pub hash: u64,
+}
+
+impl IndexedFile {
+    /// Check whether size and modification time still match `metadata`
+    pub fn is_unchanged(&self, metadata: &EntryMetadata) -> bool {
+        self.len == metadata.len && self.modified == metadata.modified
+    }
+}
+
+/// Maps each trigram to the files containing it, a search only has to
+/// read the files containing all trigrams of the search text
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct TrigramIndex {
+    files: Vec<IndexedFile>,
+    /// Sorted ids (positions in `files`) of the files containing a trigram
+    postings: BTreeMap<Trigram, Vec<u32>>,
+}
+
+impl TrigramIndex {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Number of indexed files
+    pub fn len(&self) -> usize {
+        self.files.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+
+    /// All indexed files
+    pub fn files(&self) -> &[IndexedFile] {
+        &self.files
+    }
+
+    /// Add a file with its (decompressed) contents
+    pub fn add_file(&mut self, path: &Path, metadata: &EntryMetadata, contents: &[u8]) {
+        let file = IndexedFile {
+            path: path.to_path_buf(),
+            len: metadata.len,
+            modified: metadata.modified,
+            hash: content_hash(contents),
+        };
+        self.add_trigrams(file, trigrams(contents));
+    }
+
+    fn add_trigrams(&mut self, file: IndexedFile, trigrams: impl IntoIterator<Item = Trigram>) {
+        let id = self.files.len() as u32;
+        self.files.push(file);
+
+        for trigram in trigrams {
+            self.postings.entry(trigram).or_default().push(id);
+        }
+    }
+
+    /// Trigrams of each file, the inverse of `postings`
+    fn file_trigrams(&self) -> Vec<Vec<Trigram>> {
+        let mut file_trigrams = vec![Vec::new(); self.files.len()];
+        for (trigram, ids) in &self.postings {
+            for id in ids {
+                file_trigrams[*id as usize].push(*trigram);
+            }
+        }
+        file_trigrams
+    }
+
+    /// Files that may contain `text`, i.e. all of its trigrams. Texts
+    /// shorter than a trigram match every file.
+    pub fn candidates(&self, text: &str) -> Vec<PathBuf> {
+        let wanted = trigrams(text.as_bytes());
+        if wanted.is_empty() {
+            return self.files.iter().map(|f| f.path.clone()).collect();
+        }
+
+        let mut lists = Vec::<&Vec<u32>>::new();
+        for trigram in &wanted {
+            match self.postings.get(trigram) {
+                Some(ids) => lists.push(ids),
+                None => return Vec::new(),
+            }
+        }
+
+        // intersect starting from the shortest list
+        lists.sort_by_key(|ids| ids.len());
+        let mut ids = lists[0].clone();
+        for other in &lists[1..] {
+            ids.retain(|id| other.binary_search(id).is_ok());
+        }
+
+        ids.into_iter()
+            .map(|id| self.files[id as usize].path.clone())
+            .collect()
+    }
+
+    /// Check whether the files of the index changed since it was written at
+    /// `written`, a file has another size or modification time or is gone,
+    /// or a directory holding indexed files was modified since, e.g. by
+    /// adding a file the index does not know about
+    pub fn is_stale(&self, fs: &dyn FileSystem, written: SystemTime) -> bool {
+        let mut dirs = BTreeSet::<&Path>::new();
+        for file in &self.files {
+            match fs.metadata(&file.path) {
+                Ok(metadata) if file.is_unchanged(&metadata) => {}
+                _ => return true,
+            }
+            match file.path.parent() {
+                Some(dir) if !dir.as_os_str().is_empty() => dirs.insert(dir),
+                _ => dirs.insert(Path::new(".")),
+            };
+        }
+
+        dirs.into_iter().any(|dir| {
+            fs.metadata(dir)
+                .ok()
+                .and_then(|metadata| metadata.modified)
+                .is_some_and(|modified| modified > written)
+        })
+    }
+
+    /// Write the index to a file. The new index is written completely to a
+    /// temporary file next to it and then renamed over the old one, so a
+    /// crash at any point leaves either the old or the new index behind.
+    /// An update rewrites the whole file as well, there are no segments
+    /// appended to it.
+    pub fn write_to(&self, path: &Path) -> io::Result<()> {
+        let mut temp = path.as_os_str().to_os_string();
+        temp.push(format!(".{}.tmp", std::process::id()));
+        let temp = PathBuf::from(temp);
+
+        let result = self.write_file(&temp).and_then(|_| fs::rename(&temp, path));
+        if result.is_err() {
+            let _ = fs::remove_file(&temp);
+        }
+        result?;
+
+        // make the rename itself durable
+        #[cfg(unix)]
+        {
+            let parent = match path.parent() {
+                Some(parent) if !parent.as_os_str().is_empty() => parent,
+                _ => Path::new("."),
+            };
+            fs::File::open(parent)?.sync_all()?;
+        }
+
+        Ok(())
+    }
+
+    fn write_file(&self, path: &Path) -> io::Result<()> {
+        let mut out = BufWriter::new(fs::File::create(path)?);
+        out.write_all(MAGIC)?;
+        out.write_all(&[VERSION])?;
+
+        write_u32(&mut out, self.files.len() as u32)?;
+        for file in &self.files {
+            write_bytes(&mut out, &path_to_bytes(&file.path))?;
+            write_u64(&mut out, file.len)?;
+            write_time(&mut out, file.modified)?;
+            write_u64(&mut out, file.hash)?;
+        }
+
+        write_u32(&mut out, self.postings.len() as u32)?;
+        for (trigram, ids) in &self.postings {
+            out.write_all(trigram)?;
+            write_u32(&mut out, ids.len() as u32)?;
+            for id in ids {
+                write_u32(&mut out, *id)?;
+            }
+        }
+
+        out.flush()?;
+        out.get_ref().sync_all()
+    }
+
+    /// Read an index written by `write_to`
+    pub fn read_from(path: &Path) -> io::Result<Self> {
+        let mut input = BufReader::new(fs::File::open(path)?);
+
+        let mut magic = [0u8; 8];
+        input.read_exact(&mut magic)?;
+        if &magic[..7] != MAGIC {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("{} is not a textfinder index", path.to_string_lossy()),
+            ));
+        }
+        if magic[7] != VERSION {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("index format version {} is not supported", magic[7]),
+            ));
+        }
+
+        let mut index = TrigramIndex::new();
+
+        for _ in 0..read_u32(&mut input)? {
+            index.files.push(IndexedFile {
+                path: path_from_bytes(read_bytes(&mut input)?),
+                len: read_u64(&mut input)?,
+                modified: read_time(&mut input)?,
+                hash: read_u64(&mut input)?,
+            });
+        }
+
+        for _ in 0..read_u32(&mut input)? {
+            let mut trigram = [0u8; 3];
+            input.read_exact(&mut trigram)?;
+
+            let count = read_u32(&mut input)?;
+            let mut ids = Vec::with_capacity(count.min(1 << 20) as usize);
+            for _ in 0..count {
+                let id = read_u32(&mut input)?;
+                if id as usize >= index.files.len() {
+                    return Err(Error::new(ErrorKind::InvalidData, "corrupt index"));
+                }
+                ids.push(id);
+            }
+            index.postings.insert(trigram, ids);
+        }
+
+        Ok(index)
+    }
+}
+
+/// Entries of the index an update starts from
+struct Previous {
+    ids: HashMap<PathBuf, usize>,
+    files: Vec<IndexedFile>,
+    trigrams: Vec<Vec<Trigram>>,
+}
+
+/// `DirEvent` app adding every file `DirNav` hands to it to a
+/// `TrigramIndex`, files that are not text are left out just like
+/// `TextSearch` never finds anything in them
+pub struct IndexBuilder {
+    dir: PathBuf,
+    fs: Arc<dyn FileSystem>,
+    index: TrigramIndex,
+    previous: Option<Previous>,
+    num_reused: usize,
+    num_binary: usize,
+    num_too_big: usize,
+    num_errors: usize,
+    bytes_read: u64,
+}
+
+impl DirEvent for IndexBuilder {
+    fn new() -> Self {
+        Self {
+            dir: PathBuf::new(),
+            fs: Arc::new(StdFileSystem),
+            index: TrigramIndex::new(),
+            previous: None,
+            num_reused: 0,
+            num_binary: 0,
+            num_too_big: 0,
+            num_errors: 0,
+            bytes_read: 0,
+        }
+    }
+
+    fn do_dir(&mut self, d: &Path) -> DirAction {
+        self.dir = d.to_path_buf();
+        DirAction::Descend
+    }
+
+    fn do_file(&mut self, f: &Path) {
+        let path = self.dir.join(f);
+
+        let metadata = match self.fs.metadata(&path) {
+            Ok(metadata) => metadata,
+            Err(_) => {
+                self.num_errors += 1;
+                return;
+            }
+        };
+
+        let old = self
+            .previous
+            .as_ref()
+            .and_then(|previous| previous.ids.get(&path).copied());
+
+        // same size and modification time, keep the entry without reading
+        if let Some(id) = old {
+            if self.previous.as_ref().unwrap().files[id].is_unchanged(&metadata) {
+                self.reuse(id, &metadata);
+                return;
+            }
+        }
+
+        let mut contents = String::new();
+        let result = self
+            .fs
+            .open(&path)
+            .and_then(decompress)
+            .and_then(|mut reader| reader.read_to_string(&mut contents));
+
+        match result {
+            Ok(bytes) => self.bytes_read += bytes as u64,
+            Err(e) if e.kind() == ErrorKind::InvalidData => {
+                self.num_binary += 1;
+                return;
+            }
+            Err(e) if e.kind() == ErrorKind::FileTooLarge => {
+                self.num_too_big += 1;
+                return;
+            }
+            Err(_) => {
+                self.num_errors += 1;
+                return;
+            }
+        }
+
+        // only touched, the trigrams are still valid
+        if let Some(id) = old {
+            if self.previous.as_ref().unwrap().files[id].hash == content_hash(contents.as_bytes()) {
+                self.reuse(id, &metadata);
+                return;
+            }
+        }
+
+        self.index.add_file(&path, &metadata, contents.as_bytes());
+    }
+
+    fn add_stats(&self, stats: &mut TraversalStats) {
+        // files taken over from the previous index were not indexed again
+        stats.files_searched += self.index.len() - self.num_reused;
+        stats.files_cached += self.num_reused;
+        stats.files_binary += self.num_binary;
+        stats.files_too_big += self.num_too_big;
+        stats.errors += self.num_errors;
+        stats.bytes_read += self.bytes_read;
+    }
+
+    fn set_file_system(&mut self, fs: Arc<dyn FileSystem>) {
+        self.fs = fs;
+    }
+}
+
+impl IndexBuilder {
+    /// Build on top of `previous`, files whose size and modification time
+    /// did not change are taken over without reading them and files whose
+    /// contents hash did not change keep their trigrams. Files that are not
+    /// visited again are dropped. Only reading the files is saved, the
+    /// whole index is still written out again.
+    pub fn set_previous(&mut self, previous: TrigramIndex) {
+        let trigrams = previous.file_trigrams();
+        let ids = previous
+            .files
+            .iter()
+            .enumerate()
+            .map(|(id, file)| (file.path.clone(), id))
+            .collect();
+
+        self.previous = Some(Previous {
+            ids,
+            files: previous.files,
+            trigrams,
+        });
+    }
+
+    fn reuse(&mut self, id: usize, metadata: &EntryMetadata) {
+        let previous = self.previous.as_mut().unwrap();
+        let mut file = previous.files[id].clone();
+        file.len = metadata.len;
+        file.modified = metadata.modified;
+
+        let trigrams = std::mem::take(&mut previous.trigrams[id]);
+        self.index.add_trigrams(file, trigrams);
+        self.num_reused += 1;
+    }
+
+    /// Get the index built so far
+    pub fn get_index(&self) -> &TrigramIndex {
+        &self.index
+    }
+
+    /// Number of files taken over from the previous index
+    pub fn get_num_reused(&self) -> usize {
+        self.num_reused
+    }
+
+    /// Number of files in the index the update started from that were
+    /// deleted or are no longer indexed
+    pub fn get_num_removed(&self) -> usize {
+        self.previous.as_ref().map_or(0, |previous| {
+            let kept: BTreeSet<&PathBuf> = self.index.files.iter().map(|f| &f.path).collect();
+            previous
+                .files
+                .iter()
+                .filter(|f| !kept.contains(&f.path))
+                .count()
+        })
+    }
+}
+
+impl Default for IndexBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Times are stored as seconds and nanoseconds since the epoch behind a
+/// presence flag, times before the epoch are stored as unknown
+fn write_time(out: &mut impl Write, time: Option<SystemTime>) -> io::Result<()> {
+    match time.and_then(|t| t.duration_since(SystemTime::UNIX_EPOCH).ok()) {
+        Some(since) => {
+            out.write_all(&[1])?;
+            write_u64(out, since.as_secs())?;
+            write_u32(out, since.subsec_nanos())
+        }
+        None => out.write_all(&[0]),
+    }
+}
+
+fn read_time(input: &mut impl Read) -> io::Result<Option<SystemTime>> {
+    let mut flag = [0u8; 1];
+    input.read_exact(&mut flag)?;
+    if flag[0] == 0 {
+        return Ok(None);
+    }
+
+    let secs = read_u64(input)?;
+    let nanos = read_u32(input)?;
+    Ok(SystemTime::UNIX_EPOCH.checked_add(Duration::new(secs, nanos)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dir_nav::{DirNav, EntryKind, MemoryFileSystem};
+
+    fn metadata(len: u64, secs: u64) -> EntryMetadata {
+        let mut metadata = EntryMetadata::new(EntryKind::File, len);
+        metadata.modified = SystemTime::UNIX_EPOCH.checked_add(Duration::new(secs, 5));
+        metadata
+    }
+
+    fn sample_index() -> TrigramIndex {
+        let mut index = TrigramIndex::new();
+        index.add_file(
+            Path::new("src/a.rs"),
+            &metadata(19, 1),
+            b"fn search_text() {}",
+        );
+        index.add_file(
+            Path::new("src/b.rs"),
+            &metadata(18, 2),
+            b"struct TextSearch;",
+        );
+        index.add_file(
+            Path::new("src/c.rs"),
+            &EntryMetadata::new(EntryKind::File, 14),
+            b"// search text",
+        );
+        index
+    }
+
+    fn set_file(fs: &mut MemoryFileSystem, path: &str, contents: &[u8], secs: u64) {
+        fs.add_file(Path::new(path), contents);
+        fs.set_metadata(Path::new(path), metadata(contents.len() as u64, secs))
+            .unwrap();
+    }
+
+    #[test]
+    fn test_candidates() {
+        let index = sample_index();
+
+        assert_eq!(
+            index.candidates("TextSearch"),
+            vec![PathBuf::from("src/b.rs")]
+        );
+        assert_eq!(
+            index.candidates("search"),
+            vec![PathBuf::from("src/a.rs"), PathBuf::from("src/c.rs")]
+        );
+        assert!(index.candidates("missing").is_empty());
+        assert_eq!(index.candidates("fn").len(), 3);
+    }
+
+    #[test]
+    fn test_is_stale() {
+        let mut fs = MemoryFileSystem::new();
+        set_file(&mut fs, "src/a.rs", b"fn search_text() {}", 1);
+        set_file(&mut fs, "src/b.rs", b"struct TextSearch;", 2);
+        let mut dir = EntryMetadata::new(EntryKind::Dir, 0);
+        dir.modified = metadata(0, 2).modified;
+        fs.set_metadata(Path::new("src"), dir.clone()).unwrap();
+
+        let mut index = TrigramIndex::new();
+        index.add_file(Path::new("src/a.rs"), &metadata(19, 1), b"");
+        index.add_file(Path::new("src/b.rs"), &metadata(18, 2), b"");
+        let written = metadata(0, 3).modified.unwrap();
+        assert!(!index.is_stale(&fs, written));
+
+        // a file added after the index was written
+        let mut added = fs.clone();
+        set_file(&mut added, "src/c.rs", b"", 4);
+        dir.modified = metadata(0, 4).modified;
+        added.set_metadata(Path::new("src"), dir).unwrap();
+        assert!(index.is_stale(&added, written));
+
+        let mut changed = fs.clone();
+        set_file(&mut changed, "src/a.rs", b"fn search_text() {}", 4);
+        assert!(index.is_stale(&changed, written));
+
+        fs.remove(Path::new("src/b.rs"));
+        assert!(index.is_stale(&fs, written));
+    }
+
+    #[test]
+    fn test_write_read() {
+        let index = sample_index();
+        let path = std::env::temp_dir().join(format!("textfinder-index-{}", std::process::id()));
+
+        index.write_to(&path).unwrap();
+        let read = TrigramIndex::read_from(&path);
+        fs::write(&path, b"garbage").unwrap();
+        let garbage = TrigramIndex::read_from(&path);
+        fs::write(&path, b"TFIDX\0\0\x01").unwrap();
+        let old_version = TrigramIndex::read_from(&path);
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(read.unwrap(), index);
+        assert!(garbage.is_err());
+        assert!(old_version.is_err());
+        assert!(TrigramIndex::read_from(&path).is_err());
+    }
+
+    #[test]
+    fn test_index_builder() {
+        let mut fs = MemoryFileSystem::new();
+        fs.add_file(Path::new("src/a.rs"), b"DirNav walks");
+        fs.add_file(Path::new("src/sub/b.rs"), b"TextSearch reads");
+        fs.add_file(Path::new("src/c.bin"), &[0xff, 0xfe]);
+
+        let mut dir_nav = DirNav::<IndexBuilder>::new();
+        dir_nav.set_file_system(Arc::new(fs));
+        dir_nav.set_recursive_search();
+        dir_nav.visit(Path::new("src")).unwrap();
+
+        let index = dir_nav.get_app().get_index();
+        assert_eq!(index.len(), 2);
+        assert_eq!(
+            index.candidates("TextSearch"),
+            vec![PathBuf::from("src/sub/b.rs")]
+        );
+        assert_eq!(index.files()[0].hash, content_hash(b"DirNav walks"));
+        assert_eq!(dir_nav.get_stats().files_binary, 1);
+    }
+
+    #[test]
+    fn test_update() {
+        let build = |fs: &MemoryFileSystem, previous: Option<TrigramIndex>| {
+            let mut dir_nav = DirNav::<IndexBuilder>::new();
+            dir_nav.set_file_system(Arc::new(fs.clone()));
+            if let Some(previous) = previous {
+                dir_nav.get_app().set_previous(previous);
+            }
+            dir_nav.visit(Path::new("src")).unwrap();
+            dir_nav
+        };
+
+        let mut fs = MemoryFileSystem::new();
+        set_file(&mut fs, "src/same.rs", b"unchanged text", 1);
+        set_file(&mut fs, "src/touched.rs", b"touched text", 1);
+        set_file(&mut fs, "src/edited.rs", b"old text", 1);
+        set_file(&mut fs, "src/deleted.rs", b"deleted text", 1);
+
+        let mut dir_nav = build(&fs, None);
+        let first = dir_nav.get_app().get_index().clone();
+        assert_eq!(first.len(), 4);
+
+        // contents that differ but would not be read if size and time match
+        set_file(&mut fs, "src/same.rs", b"UNCHANGED TEXT", 1);
+        set_file(&mut fs, "src/touched.rs", b"touched text", 2);
+        set_file(&mut fs, "src/edited.rs", b"new text", 2);
+        set_file(&mut fs, "src/added.rs", b"added text", 2);
+        fs.remove(Path::new("src/deleted.rs"));
+
+        let mut dir_nav = build(&fs, Some(first));
+        assert_eq!(dir_nav.get_stats().files_searched, 2);
+        assert_eq!(dir_nav.get_stats().files_cached, 2);
+        let app = dir_nav.get_app();
+        assert_eq!(app.get_num_reused(), 2);
+        assert_eq!(app.get_num_removed(), 1);
+
+        let index = app.get_index();
+        assert_eq!(index.len(), 4);
+        assert_eq!(index.candidates("unchanged").len(), 1);
+        assert_eq!(index.candidates("UNCHANGED").len(), 0);
+        assert_eq!(index.candidates("touched").len(), 1);
+        assert_eq!(index.candidates("old text").len(), 0);
+        assert_eq!(
+            index.candidates("new text"),
+            vec![PathBuf::from("src/edited.rs")]
+        );
+        assert_eq!(index.candidates("added").len(), 1);
+        assert_eq!(index.candidates("deleted").len(), 0);
+
+        let touched = index
+            .files()
+            .iter()
+            .find(|f| f.path == Path::new("src/touched.rs"))
+            .unwrap();
+        assert_eq!(touched.modified, metadata(0, 2).modified);
+    }
+}
@@ -0,0 +1,250 @@
+use bin_io::{
+    path_from_bytes, path_to_bytes, read_bytes, read_u32, read_u64, write_bytes, write_u32,
+    write_u64,
+};
+use dir_nav::EntryMetadata;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{self, BufReader, BufWriter, Error, ErrorKind, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+/// File name of the result cache when no other is given
+pub const DEFAULT_CACHE_FILE: &str = ".textfinder-cache";
+
+/// Magic bytes and format version at the start of a cache file
+const MAGIC: &[u8; 8] = b"TFCACHE\x01";
+
+/// Queries kept in a cache file, the ones run longest ago are dropped
+/// first so the file does not grow with every new search text
+const MAX_CACHED_QUERIES: usize = 32;
+
+/// Result of searching a file in the state it had at that time
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct CachedFile {
+    len: u64,
+    modified: SystemTime,
+    found: bool,
+}
+
+/// Results of earlier runs keyed by query and file, a file whose size and
+/// modification time still match the cached ones does not have to be
+/// searched again
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ResultCache {
+    /// Results read from the cache file
+    queries: BTreeMap<String, BTreeMap<PathBuf, CachedFile>>,
+    /// Queries read from the cache file, the most recently run first
+    order: Vec<String>,
+    /// Results recorded in this run, they replace the ones read for the
+    /// same query so deleted files drop out. Queries not run this time are
+    /// written back after them, up to `MAX_CACHED_QUERIES` in all.
+    updated: BTreeMap<String, BTreeMap<PathBuf, CachedFile>>,
+}
+
+impl ResultCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Cached result of `query` for the file, if the file is unchanged
+    pub fn lookup(&self, query: &str, path: &Path, metadata: &EntryMetadata) -> Option<bool> {
+        let file = self.queries.get(query)?.get(path)?;
+        if file.len == metadata.len && Some(file.modified) == metadata.modified {
+            Some(file.found)
+        } else {
+            None
+        }
+    }
+
+    /// Record the result of `query` for the file, files without a known
+    /// modification time are not cached
+    pub fn record(&mut self, query: &str, path: &Path, metadata: &EntryMetadata, found: bool) {
+        if let Some(modified) = metadata.modified {
+            let file = CachedFile {
+                len: metadata.len,
+                modified,
+                found,
+            };
+            self.updated
+                .entry(query.to_string())
+                .or_default()
+                .insert(path.to_path_buf(), file);
+        }
+    }
+
+    /// Write the results of this run and of the queries read that were not
+    /// run again to a file, replacing it atomically
+    pub fn write_to(&self, path: &Path) -> io::Result<()> {
+        let mut temp = path.as_os_str().to_os_string();
+        temp.push(format!(".{}.tmp", std::process::id()));
+        let temp = PathBuf::from(temp);
+
+        let older = self
+            .order
+            .iter()
+            .filter(|query| !self.updated.contains_key(*query))
+            .filter_map(|query| Some((query, self.queries.get(query)?)));
+        let queries: Vec<_> = self
+            .updated
+            .iter()
+            .chain(older)
+            .take(MAX_CACHED_QUERIES)
+            .collect();
+
+        let result = write_queries(&queries, &temp).and_then(|_| fs::rename(&temp, path));
+        if result.is_err() {
+            let _ = fs::remove_file(&temp);
+        }
+        result
+    }
+
+    /// Read a cache written by `write_to`
+    pub fn read_from(path: &Path) -> io::Result<Self> {
+        let mut input = BufReader::new(fs::File::open(path)?);
+
+        let mut magic = [0u8; 8];
+        input.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("{} is not a textfinder cache", path.to_string_lossy()),
+            ));
+        }
+
+        let mut cache = ResultCache::new();
+        for _ in 0..read_u32(&mut input)? {
+            let query = String::from_utf8(read_bytes(&mut input)?)
+                .map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+
+            let mut files = BTreeMap::new();
+            for _ in 0..read_u32(&mut input)? {
+                let path = path_from_bytes(read_bytes(&mut input)?);
+                let len = read_u64(&mut input)?;
+                let secs = read_u64(&mut input)?;
+                let nanos = read_u32(&mut input)?;
+                let mut found = [0u8; 1];
+                input.read_exact(&mut found)?;
+
+                let modified = SystemTime::UNIX_EPOCH
+                    .checked_add(Duration::new(secs, nanos))
+                    .ok_or_else(|| Error::new(ErrorKind::InvalidData, "corrupt cache"))?;
+                let file = CachedFile {
+                    len,
+                    modified,
+                    found: found[0] != 0,
+                };
+                files.insert(path, file);
+            }
+            cache.order.push(query.clone());
+            cache.queries.insert(query, files);
+        }
+
+        Ok(cache)
+    }
+}
+
+fn write_queries(
+    queries: &[(&String, &BTreeMap<PathBuf, CachedFile>)],
+    path: &Path,
+) -> io::Result<()> {
+    let mut out = BufWriter::new(fs::File::create(path)?);
+    out.write_all(MAGIC)?;
+
+    write_u32(&mut out, queries.len() as u32)?;
+    for (query, files) in queries {
+        write_bytes(&mut out, query.as_bytes())?;
+
+        // times before the epoch cannot be stored, those files are
+        // searched again next time
+        let files: Vec<_> = files
+            .iter()
+            .filter_map(|(path, file)| {
+                let since = file.modified.duration_since(SystemTime::UNIX_EPOCH).ok()?;
+                Some((path, file, since))
+            })
+            .collect();
+
+        write_u32(&mut out, files.len() as u32)?;
+        for (path, file, since) in files {
+            write_bytes(&mut out, &path_to_bytes(path))?;
+            write_u64(&mut out, file.len)?;
+            write_u64(&mut out, since.as_secs())?;
+            write_u32(&mut out, since.subsec_nanos())?;
+            out.write_all(&[file.found as u8])?;
+        }
+    }
+
+    out.flush()?;
+    out.get_ref().sync_all()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dir_nav::EntryKind;
+
+    fn metadata(len: u64, secs: u64) -> EntryMetadata {
+        let mut metadata = EntryMetadata::new(EntryKind::File, len);
+        metadata.modified = SystemTime::UNIX_EPOCH.checked_add(Duration::new(secs, 7));
+        metadata
+    }
+
+    #[test]
+    fn test_result_cache() {
+        let (a, b) = (Path::new("src/a.rs"), Path::new("src/b.rs"));
+        let mut cache = ResultCache::new();
+        cache.record("text", a, &metadata(10, 1), true);
+        cache.record("text", b, &metadata(20, 1), false);
+        cache.record("other", a, &metadata(10, 1), false);
+        cache.record(
+            "text",
+            Path::new("no-time"),
+            &EntryMetadata::new(EntryKind::File, 1),
+            true,
+        );
+
+        let path = std::env::temp_dir().join(format!(
+            "textfinder-cache-result-cache-{}",
+            std::process::id()
+        ));
+        cache.write_to(&path).unwrap();
+        let mut read = ResultCache::read_from(&path).unwrap();
+
+        assert_eq!(read.lookup("text", a, &metadata(10, 1)), Some(true));
+        assert_eq!(read.lookup("text", b, &metadata(20, 1)), Some(false));
+        assert_eq!(read.lookup("other", a, &metadata(10, 1)), Some(false));
+        assert_eq!(read.lookup("text", a, &metadata(10, 2)), None);
+        assert_eq!(read.lookup("text", a, &metadata(11, 1)), None);
+        assert_eq!(read.lookup("missing", a, &metadata(10, 1)), None);
+        assert_eq!(
+            read.lookup("text", Path::new("no-time"), &metadata(1, 0)),
+            None
+        );
+
+        // a new run of `text` replaces its results, b was deleted, `other`
+        // was not run again and is kept
+        read.record("text", a, &metadata(10, 2), false);
+        read.write_to(&path).unwrap();
+        let mut read = ResultCache::read_from(&path).unwrap();
+        assert_eq!(read.order, ["text", "other"]);
+
+        // only the most recently run queries are kept
+        for i in 0..MAX_CACHED_QUERIES {
+            read.record(&format!("new {}", i), a, &metadata(10, 2), true);
+        }
+        read.write_to(&path).unwrap();
+        let pruned = ResultCache::read_from(&path).unwrap();
+        assert_eq!(pruned.order.len(), MAX_CACHED_QUERIES);
+        assert_eq!(pruned.lookup("text", a, &metadata(10, 2)), None);
+        assert_eq!(pruned.lookup("new 0", a, &metadata(10, 2)), Some(true));
+        fs::write(&path, b"garbage").unwrap();
+        let garbage = ResultCache::read_from(&path);
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(read.lookup("text", a, &metadata(10, 2)), Some(false));
+        assert_eq!(read.lookup("text", b, &metadata(20, 1)), None);
+        assert_eq!(read.lookup("other", a, &metadata(10, 1)), Some(false));
+        assert!(garbage.is_err());
+    }
+}
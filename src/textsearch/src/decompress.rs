@@ -0,0 +1,235 @@
+use std::io::{self, Cursor, Read};
+use std::sync::{Arc, Mutex};
+
+/// Largest decompressed contents read from a file, reading further fails
+pub const MAX_DECOMPRESSED_SIZE: u64 = 1024 * 1024 * 1024;
+
+/// Compression formats recognised by their magic bytes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    Gzip,
+    Bzip2,
+    Xz,
+    Zstd,
+}
+
+/// Magic bytes at the start of each format, longest is xz. bzip2 is
+/// checked on its own as its magic is plain text.
+const MAGIC: &[(Compression, &[u8])] = &[
+    (Compression::Gzip, &[0x1f, 0x8b]),
+    (Compression::Xz, &[0xfd, b'7', b'z', b'X', b'Z', 0x00]),
+    (Compression::Zstd, &[0x28, 0xb5, 0x2f, 0xfd]),
+];
+
+impl Compression {
+    /// Format whose magic bytes `head` starts with
+    pub fn detect(head: &[u8]) -> Option<Self> {
+        // `BZh` and the block size, 1 to 9 hundred kB
+        if head.len() >= 4 && head.starts_with(b"BZh") && (b'1'..=b'9').contains(&head[3]) {
+            return Some(Compression::Bzip2);
+        }
+
+        MAGIC
+            .iter()
+            .find(|(_, magic)| head.starts_with(magic))
+            .map(|(compression, _)| *compression)
+    }
+}
+
+/// Wrap `reader` in a streaming decoder if its contents start with the
+/// magic bytes of a known compression format, other contents are passed
+/// through unchanged. Contents that cannot be decoded from the start are
+/// read as they are, and decoding fails after `MAX_DECOMPRESSED_SIZE`
+/// bytes.
+pub fn decompress(reader: Box<dyn Read + Send>) -> io::Result<Box<dyn Read + Send>> {
+    decompress_limited(reader, MAX_DECOMPRESSED_SIZE).map(|(reader, _)| reader)
+}
+
+/// Same as `decompress`, also returns the format the contents were decoded
+/// from, `None` if they are read as they are
+pub fn decompress_format(
+    reader: Box<dyn Read + Send>,
+) -> io::Result<(Box<dyn Read + Send>, Option<Compression>)> {
+    decompress_limited(reader, MAX_DECOMPRESSED_SIZE)
+}
+
+fn decompress_limited(
+    mut reader: Box<dyn Read + Send>,
+    limit: u64,
+) -> io::Result<(Box<dyn Read + Send>, Option<Compression>)> {
+    let mut head = Vec::with_capacity(6);
+    reader.by_ref().take(6).read_to_end(&mut head)?;
+
+    let compression = match Compression::detect(&head) {
+        Some(compression) => compression,
+        None => return Ok((Box::new(Cursor::new(head).chain(reader)), None)),
+    };
+
+    // keep a copy of the raw bytes until the decoder has produced the first
+    // byte, to read them again if the magic bytes were a coincidence
+    let tape = Tape(Arc::new(Mutex::new(TapeState {
+        reader: Box::new(Cursor::new(head).chain(reader)),
+        copy: Some(Vec::new()),
+    })));
+
+    let decoder: io::Result<Box<dyn Read + Send>> = match compression {
+        Compression::Gzip => Ok(Box::new(flate2::read::MultiGzDecoder::new(tape.clone()))),
+        Compression::Bzip2 => Ok(Box::new(bzip2::read::MultiBzDecoder::new(tape.clone()))),
+        Compression::Xz => Ok(Box::new(xz2::read::XzDecoder::new_multi_decoder(
+            tape.clone(),
+        ))),
+        Compression::Zstd => zstd::stream::read::Decoder::new(tape.clone())
+            .map(|decoder| Box::new(decoder) as Box<dyn Read + Send>),
+    };
+
+    let mut first = Vec::with_capacity(1);
+    let decoded = decoder.and_then(|mut decoder| {
+        decoder.by_ref().take(1).read_to_end(&mut first)?;
+        Ok(decoder)
+    });
+
+    match decoded {
+        Ok(decoder) => {
+            tape.0.lock().unwrap().copy = None;
+            let reader = Limit {
+                reader: Cursor::new(first).chain(decoder),
+                left: limit,
+            };
+            Ok((Box::new(reader), Some(compression)))
+        }
+        Err(_) => {
+            let mut state = tape.0.lock().unwrap();
+            let copy = state.copy.take().unwrap_or_default();
+            let reader = std::mem::replace(&mut state.reader, Box::new(io::empty()));
+            Ok((Box::new(Cursor::new(copy).chain(reader)), None))
+        }
+    }
+}
+
+/// Raw contents shared with a decoder, with a copy of what was read so far
+struct TapeState {
+    reader: Box<dyn Read + Send>,
+    copy: Option<Vec<u8>>,
+}
+
+#[derive(Clone)]
+struct Tape(Arc<Mutex<TapeState>>);
+
+impl Read for Tape {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut state = self.0.lock().unwrap();
+        let n = state.reader.read(buf)?;
+        if let Some(copy) = state.copy.as_mut() {
+            copy.extend_from_slice(&buf[..n]);
+        }
+        Ok(n)
+    }
+}
+
+/// Reader failing with `FileTooLarge` once more than `left` bytes are read
+struct Limit<R> {
+    reader: R,
+    left: u64,
+}
+
+impl<R: Read> Read for Limit<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.reader.read(buf)?;
+        if n as u64 > self.left {
+            return Err(io::Error::new(
+                io::ErrorKind::FileTooLarge,
+                "decompressed contents are too large",
+            ));
+        }
+        self.left -= n as u64;
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    fn read_all(contents: Vec<u8>) -> String {
+        let mut text = String::new();
+        decompress(Box::new(Cursor::new(contents)))
+            .unwrap()
+            .read_to_string(&mut text)
+            .unwrap();
+        text
+    }
+
+    #[test]
+    fn test_decompress() {
+        let text = "ERROR disk full\n";
+
+        let mut gz = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        gz.write_all(text.as_bytes()).unwrap();
+        assert_eq!(read_all(gz.finish().unwrap()), text);
+
+        let mut bz = bzip2::write::BzEncoder::new(Vec::new(), bzip2::Compression::default());
+        bz.write_all(text.as_bytes()).unwrap();
+        assert_eq!(read_all(bz.finish().unwrap()), text);
+
+        let mut xz = xz2::write::XzEncoder::new(Vec::new(), 6);
+        xz.write_all(text.as_bytes()).unwrap();
+        assert_eq!(read_all(xz.finish().unwrap()), text);
+
+        let zst = zstd::stream::encode_all(text.as_bytes(), 0).unwrap();
+        assert_eq!(read_all(zst), text);
+
+        // plain and very short contents pass through
+        assert_eq!(read_all(text.as_bytes().to_vec()), text);
+        assert_eq!(read_all(b"B".to_vec()), "B");
+
+        // text that starts like a compressed file is read as it is
+        assert_eq!(
+            read_all(b"BZh9 is not bzip2\n".to_vec()),
+            "BZh9 is not bzip2\n"
+        );
+        let mut fake = vec![0x1f, 0x8b];
+        fake.extend_from_slice(text.as_bytes());
+        let mut contents = Vec::new();
+        decompress(Box::new(Cursor::new(fake.clone())))
+            .unwrap()
+            .read_to_end(&mut contents)
+            .unwrap();
+        assert_eq!(contents, fake);
+    }
+
+    #[test]
+    fn test_decompress_limit() {
+        let mut gz = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        gz.write_all(&[b'a'; 100]).unwrap();
+        let gz = gz.finish().unwrap();
+
+        let mut text = Vec::new();
+        decompress_limited(Box::new(Cursor::new(gz.clone())), 100)
+            .unwrap()
+            .0
+            .read_to_end(&mut text)
+            .unwrap();
+        assert_eq!(text.len(), 100);
+
+        let err = decompress_limited(Box::new(Cursor::new(gz)), 99)
+            .unwrap()
+            .0
+            .read_to_end(&mut text)
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::FileTooLarge);
+    }
+
+    #[test]
+    fn test_detect() {
+        assert_eq!(
+            Compression::detect(&[0x1f, 0x8b, 8]),
+            Some(Compression::Gzip)
+        );
+        assert_eq!(Compression::detect(b"BZh91AY"), Some(Compression::Bzip2));
+        assert_eq!(Compression::detect(b"BZ"), None);
+        assert_eq!(Compression::detect(b"BZh is text"), None);
+        assert_eq!(Compression::detect(b"BZh0"), None);
+        assert_eq!(Compression::detect(b"plain text"), None);
+    }
+}
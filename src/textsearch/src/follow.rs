@@ -0,0 +1,257 @@
+use dir_nav::{EntryMetadata, FileSystem};
+use std::collections::{BTreeMap, VecDeque};
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+
+/// Rotated files whose position is kept, the ones rotated longest ago are
+/// forgotten first and read from the start if they turn up again
+const MAX_ROTATED: usize = 64;
+
+/// Position up to which a followed file has been read
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Position {
+    /// Inode the position belongs to, a new inode means the file was rotated
+    inode: Option<u64>,
+    /// Offset just after the last complete line read
+    offset: u64,
+    /// Number of complete lines read
+    line_number: usize,
+}
+
+/// Files being followed like `tail -F`, lines appended to them are read on
+/// each `poll`, truncation restarts at the top and a replaced file (new
+/// inode) is read from its start, or from where it was left if it was
+/// followed under another name before
+#[derive(Debug, Default)]
+pub struct Follower {
+    files: BTreeMap<PathBuf, Position>,
+
+    /// Positions in files that were rotated away with their inode, so the
+    /// rotated file continues where it was left once it is found again.
+    /// Entries are dropped when found and at most `MAX_ROTATED` are kept.
+    rotated: VecDeque<(u64, Position)>,
+}
+
+impl Follower {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Check whether a file is already followed
+    pub fn contains(&self, path: &Path) -> bool {
+        self.files.contains_key(path)
+    }
+
+    /// Number of files being followed
+    pub fn len(&self) -> usize {
+        self.files.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+
+    /// Follow a file whose `contents` were searched already, reading resumes
+    /// after its last complete line
+    pub fn add_searched(&mut self, path: &Path, metadata: &EntryMetadata, contents: &[u8]) {
+        let offset = contents
+            .iter()
+            .rposition(|&b| b == b'\n')
+            .map_or(0, |pos| pos + 1);
+
+        let position = Position {
+            inode: metadata.inode,
+            offset: offset as u64,
+            line_number: contents[..offset].iter().filter(|&&b| b == b'\n').count(),
+        };
+        self.files.insert(path.to_path_buf(), position);
+    }
+
+    /// Follow a file that showed up after the initial search, it is read
+    /// from the start on the next poll unless it is a rotated file that was
+    /// followed under another name
+    pub fn add_new(&mut self, path: &Path, metadata: &EntryMetadata) {
+        let position = take_rotated(&mut self.rotated, metadata.inode).unwrap_or(Position {
+            inode: metadata.inode,
+            offset: 0,
+            line_number: 0,
+        });
+        self.files.insert(path.to_path_buf(), position);
+    }
+
+    /// Read the complete lines appended to each followed file since the
+    /// last poll, `on_line` gets the path, line number and text of each.
+    /// Files that cannot be read right now, e.g. while being rotated, are
+    /// tried again on the next poll.
+    pub fn poll(&mut self, fs: &dyn FileSystem, mut on_line: impl FnMut(&Path, usize, &str)) {
+        for (path, position) in self.files.iter_mut() {
+            let metadata = match fs.metadata(path) {
+                Ok(metadata) if metadata.is_file() => metadata,
+                _ => continue,
+            };
+
+            if metadata.inode != position.inode {
+                // rotated, the old file may turn up under a new name
+                if let Some(inode) = position.inode {
+                    self.rotated.retain(|(rotated, _)| *rotated != inode);
+                    self.rotated.push_back((inode, *position));
+                    if self.rotated.len() > MAX_ROTATED {
+                        self.rotated.pop_front();
+                    }
+                }
+                // the file now here may be one rotated away from another
+                // followed name
+                *position = take_rotated(&mut self.rotated, metadata.inode).unwrap_or(Position {
+                    inode: metadata.inode,
+                    offset: 0,
+                    line_number: 0,
+                });
+            } else if metadata.len < position.offset {
+                // truncated in place, e.g. by `copytruncate`
+                position.offset = 0;
+                position.line_number = 0;
+            }
+
+            if metadata.len == position.offset {
+                continue;
+            }
+
+            let reader = match fs.open_at(path, position.offset) {
+                Ok(reader) => reader,
+                Err(_) => continue,
+            };
+
+            let mut reader = BufReader::new(reader);
+            let mut buffer = Vec::new();
+            loop {
+                buffer.clear();
+                match reader.read_until(b'\n', &mut buffer) {
+                    // an incomplete last line is read again once finished
+                    Ok(bytes) if bytes > 0 && buffer.ends_with(b"\n") => {
+                        position.offset += bytes as u64;
+                        position.line_number += 1;
+
+                        let line = String::from_utf8_lossy(&buffer);
+                        on_line(
+                            path,
+                            position.line_number,
+                            line.trim_end_matches(['\n', '\r']),
+                        );
+                    }
+                    _ => break,
+                }
+            }
+        }
+    }
+}
+
+/// Remove and return the position kept for a rotated file
+fn take_rotated(rotated: &mut VecDeque<(u64, Position)>, inode: Option<u64>) -> Option<Position> {
+    let inode = inode?;
+    let index = rotated.iter().position(|(rotated, _)| *rotated == inode)?;
+    rotated.remove(index).map(|(_, position)| position)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dir_nav::{EntryKind, MemoryFileSystem};
+
+    fn poll(follower: &mut Follower, fs: &MemoryFileSystem) -> Vec<(String, usize, String)> {
+        let mut lines = Vec::new();
+        follower.poll(fs, |path, number, line| {
+            lines.push((
+                path.to_string_lossy().into_owned(),
+                number,
+                line.to_string(),
+            ))
+        });
+        lines
+    }
+
+    fn line(path: &str, number: usize, text: &str) -> (String, usize, String) {
+        (path.to_string(), number, text.to_string())
+    }
+
+    fn set_inode(fs: &mut MemoryFileSystem, path: &str, contents: &[u8], inode: u64) {
+        fs.add_file(Path::new(path), contents);
+        let mut metadata = EntryMetadata::new(EntryKind::File, contents.len() as u64);
+        metadata.inode = Some(inode);
+        fs.set_metadata(Path::new(path), metadata).unwrap();
+    }
+
+    #[test]
+    fn test_follow_append_and_truncate() {
+        let mut fs = MemoryFileSystem::new();
+        set_inode(&mut fs, "app.log", b"one\ntwo\nthr", 1);
+
+        let mut follower = Follower::new();
+        let metadata = fs.metadata(Path::new("app.log")).unwrap();
+        follower.add_searched(Path::new("app.log"), &metadata, b"one\ntwo\nthr");
+        assert!(poll(&mut follower, &fs).is_empty());
+
+        // the incomplete line is reported once it is finished
+        set_inode(&mut fs, "app.log", b"one\ntwo\nthree\nfour\n", 1);
+        assert_eq!(
+            poll(&mut follower, &fs),
+            vec![line("app.log", 3, "three"), line("app.log", 4, "four")]
+        );
+
+        set_inode(&mut fs, "app.log", b"new\n", 1);
+        assert_eq!(poll(&mut follower, &fs), vec![line("app.log", 1, "new")]);
+    }
+
+    #[test]
+    fn test_follow_rotation() {
+        let mut fs = MemoryFileSystem::new();
+        set_inode(&mut fs, "app.log", b"old\n", 1);
+
+        let mut follower = Follower::new();
+        let metadata = fs.metadata(Path::new("app.log")).unwrap();
+        follower.add_searched(Path::new("app.log"), &metadata, b"old\n");
+
+        // app.log got one more line, then was moved to app.log.1
+        fs.remove(Path::new("app.log"));
+        set_inode(&mut fs, "app.log.1", b"old\nlast\n", 1);
+        set_inode(&mut fs, "app.log", b"first\n", 2);
+
+        assert_eq!(poll(&mut follower, &fs), vec![line("app.log", 1, "first")]);
+
+        let metadata = fs.metadata(Path::new("app.log.1")).unwrap();
+        follower.add_new(Path::new("app.log.1"), &metadata);
+        assert_eq!(follower.len(), 2);
+        assert!(follower.rotated.is_empty());
+        assert_eq!(poll(&mut follower, &fs), vec![line("app.log.1", 2, "last")]);
+
+        // a rotation into the followed app.log.1 continues where app.log was
+        set_inode(&mut fs, "app.log", b"first\nsecond\n", 2);
+        fs.remove(Path::new("app.log.1"));
+        set_inode(&mut fs, "app.log.1", b"first\nsecond\n", 2);
+        set_inode(&mut fs, "app.log", b"", 3);
+        assert_eq!(
+            poll(&mut follower, &fs),
+            vec![line("app.log.1", 2, "second")]
+        );
+    }
+
+    #[test]
+    fn test_follow_rotated_limit() {
+        let mut fs = MemoryFileSystem::new();
+        set_inode(&mut fs, "app.log", b"", 0);
+
+        let mut follower = Follower::new();
+        let metadata = fs.metadata(Path::new("app.log")).unwrap();
+        follower.add_searched(Path::new("app.log"), &metadata, b"");
+
+        // rotated files that never turn up again are not kept forever
+        for inode in 1..=2 * MAX_ROTATED as u64 {
+            set_inode(&mut fs, "app.log", b"", inode);
+            poll(&mut follower, &fs);
+        }
+        assert_eq!(follower.rotated.len(), MAX_ROTATED);
+        assert_eq!(
+            follower.rotated.front().map(|(inode, _)| *inode),
+            Some(MAX_ROTATED as u64)
+        );
+    }
+}
@@ -1,11 +1,29 @@
-use dir_nav::DirEvent;
+use dir_nav::{DirAction, DirEvent, FileSystem, StdFileSystem, TraversalStats};
 use std::io::prelude::*;
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+mod cache;
+mod decompress;
+mod follow;
+mod watch;
+pub use cache::{ResultCache, DEFAULT_CACHE_FILE};
+pub use decompress::{decompress, decompress_format, Compression, MAX_DECOMPRESSED_SIZE};
+pub use follow::Follower;
+pub use watch::Watcher;
 
 pub trait SearchEvent {
     fn new() -> Self;
     fn set_dir(&mut self, dir: &Path);
     fn set_file(&mut self, result: (&Path, bool, &str));
+
+    /// Called with the name, line number and text of each matching line
+    /// when a stream is searched line by line
+    fn set_line(&mut self, _result: (&Path, usize, &str)) {}
+
+    /// Called when a repeated search finds the text in a file it was not in
+    /// before (`true`) or no longer finds it (`false`)
+    fn set_change(&mut self, _result: (&Path, bool)) {}
 }
 
 pub struct TextSearch<T: SearchEvent> {
@@ -13,6 +31,19 @@ pub struct TextSearch<T: SearchEvent> {
     search_text: String,
     out: T,
     num_found: usize,
+    max_found: Option<usize>,
+    num_searched: usize,
+    num_binary: usize,
+    num_too_big: usize,
+    num_errors: usize,
+    bytes_read: u64,
+    fs: Arc<dyn FileSystem>,
+    decompress: bool,
+    follower: Option<Follower>,
+    following: bool,
+    watcher: Option<Watcher>,
+    cache: Option<ResultCache>,
+    num_cached: usize,
 }
 
 impl<T: SearchEvent> DirEvent for TextSearch<T> {
@@ -22,42 +53,183 @@ impl<T: SearchEvent> DirEvent for TextSearch<T> {
             search_text: String::new(),
             out: T::new(),
             num_found: 0,
+            max_found: None,
+            num_searched: 0,
+            num_binary: 0,
+            num_too_big: 0,
+            num_errors: 0,
+            bytes_read: 0,
+            fs: Arc::new(StdFileSystem),
+            decompress: true,
+            follower: None,
+            following: false,
+            watcher: None,
+            cache: None,
+            num_cached: 0,
         }
     }
 
-    fn do_dir(&mut self, path: &Path) {
+    fn do_dir(&mut self, path: &Path) -> DirAction {
         self.dir = path.to_path_buf();
         self.out.set_dir(path);
+        DirAction::Descend
     }
 
     fn do_file(&mut self, file_name: &Path) {
         let file_path = self.dir.join(file_name);
 
-        let file = std::fs::File::open(file_path);
+        if let Some(follower) = &mut self.follower {
+            if follower.contains(&file_path) {
+                return;
+            }
+
+            // files created after the initial search are read by polling,
+            // compressed ones such as rotated logs have no lines to add
+            if self.following {
+                if let Ok(metadata) = self.fs.metadata(&file_path) {
+                    if !(self.decompress && is_compressed(&*self.fs, &file_path)) {
+                        follower.add_new(&file_path, &metadata);
+                    }
+                }
+                return;
+            }
+        }
+
+        let watched = match &mut self.watcher {
+            Some(watcher) => match self.fs.metadata(&file_path) {
+                Ok(metadata) if watcher.is_unchanged(&file_path, &metadata) => return,
+                Ok(metadata) => Some(metadata),
+                Err(_) => None,
+            },
+            None => None,
+        };
+
+        let cached = match &self.cache {
+            Some(cache) => self.fs.metadata(&file_path).ok().map(|metadata| {
+                let found = cache.lookup(&self.cache_query(), &file_path, &metadata);
+                (metadata, found)
+            }),
+            None => None,
+        };
+
+        // unchanged since the cached run, replay its result
+        if let Some((metadata, Some(found))) = &cached {
+            self.num_cached += 1;
+            if *found {
+                self.num_found += 1;
+            }
 
-        if file.is_err() {
-            self.out.set_file((file_name, false, "Cannot Open File"));
+            let query = self.cache_query();
+            if let Some(cache) = &mut self.cache {
+                cache.record(&query, &file_path, metadata, *found);
+            }
+            self.out.set_file((file_name, *found, &self.search_text));
             return;
         }
 
-        let mut file = file.unwrap();
+        let mut file = self.fs.open(&file_path);
+        let mut compression = None;
+
+        if self.decompress {
+            file = file.and_then(decompress_format).map(|(reader, format)| {
+                compression = format;
+                reader
+            });
+        }
+
+        let mut file = match file {
+            Ok(file) => file,
+            Err(e) => {
+                self.count_error(&e);
+                self.out.set_file((file_name, false, "Cannot Open File"));
+                return;
+            }
+        };
         let mut contents = String::new();
 
-        let result = file.read_to_string(&mut contents);
+        let result = match compression {
+            // decompressed contents can be far larger than the file, they
+            // are searched as they are decoded instead of read into memory
+            Some(_) => search_chunks(file, self.search_text.as_bytes()),
+            None => file
+                .read_to_string(&mut contents)
+                .map(|bytes| (contents.contains(&self.search_text), bytes as u64)),
+        };
 
-        if result.is_ok() {
-            let found: bool = contents.contains(&self.search_text);
+        match &result {
+            Ok((_, bytes)) => {
+                self.num_searched += 1;
+                self.bytes_read += *bytes;
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::InvalidData => self.num_binary += 1,
+            Err(e) => self.count_error(e),
+        }
 
+        if let Ok((found, _)) = result {
             if found {
                 self.num_found += 1;
             }
 
-            self.out.set_file((file_name, found, &self.search_text));
+            if let Some((metadata, _)) = &cached {
+                let query = self.cache_query();
+                if let Some(cache) = &mut self.cache {
+                    cache.record(&query, &file_path, metadata, found);
+                }
+            }
+
+            match (&mut self.watcher, &watched) {
+                (Some(watcher), Some(metadata)) if watcher.get_pass() > 0 => {
+                    if let Some(change) = watcher.record(&file_path, metadata, found) {
+                        self.out.set_change((&file_path, change));
+                    }
+                }
+                (Some(watcher), Some(metadata)) => {
+                    watcher.record(&file_path, metadata, found);
+                    self.out.set_file((file_name, found, &self.search_text));
+                }
+                _ => self.out.set_file((file_name, found, &self.search_text)),
+            }
+
+            // offsets in decompressed contents do not point into the file,
+            // compressed files are searched once and not followed
+            if let (Some(follower), None) = (&mut self.follower, compression) {
+                if let Ok(metadata) = self.fs.metadata(&file_path) {
+                    follower.add_searched(&file_path, &metadata, contents.as_bytes());
+                }
+            }
         }
     }
+
+    fn done(&self) -> bool {
+        self.max_found.is_some_and(|max| self.num_found >= max)
+    }
+
+    fn set_file_system(&mut self, fs: Arc<dyn FileSystem>) {
+        self.fs = fs;
+    }
+
+    fn add_stats(&self, stats: &mut TraversalStats) {
+        stats.files_searched += self.num_searched;
+        stats.files_found += self.num_found;
+        stats.files_cached += self.num_cached;
+        stats.files_binary += self.num_binary;
+        stats.files_too_big += self.num_too_big;
+        stats.errors += self.num_errors;
+        stats.bytes_read += self.bytes_read;
+    }
 }
 
 impl<T: SearchEvent> TextSearch<T> {
+    /// Count a file that could not be read, streams decompressed past
+    /// `MAX_DECOMPRESSED_SIZE` are counted as too big rather than as errors
+    fn count_error(&mut self, e: &std::io::Error) {
+        if e.kind() == std::io::ErrorKind::FileTooLarge {
+            self.num_too_big += 1;
+        } else {
+            self.num_errors += 1;
+        }
+    }
+
     pub fn set_search_text(&mut self, search_text: &str) {
         self.search_text = search_text.to_string();
     }
@@ -69,6 +241,115 @@ impl<T: SearchEvent> TextSearch<T> {
     pub fn get_num_found(&self) -> usize {
         self.num_found
     }
+
+    /// Decompress gzip, bzip2, xz and zstd files, detected by their magic
+    /// bytes, before searching them, on by default
+    pub fn set_decompress(&mut self, decompress: bool) {
+        self.decompress = decompress;
+    }
+
+    /// Search a stream such as stdin line by line, each matching line is
+    /// reported with its line number as soon as it is read
+    pub fn search_lines(&mut self, name: &Path, mut reader: impl BufRead) -> std::io::Result<()> {
+        let mut buffer = Vec::new();
+        let mut line_number = 0;
+        let mut found = false;
+
+        loop {
+            buffer.clear();
+            let bytes = reader.read_until(b'\n', &mut buffer)?;
+            if bytes == 0 {
+                break;
+            }
+
+            line_number += 1;
+            self.bytes_read += bytes as u64;
+
+            let line = String::from_utf8_lossy(&buffer);
+            let line = line.trim_end_matches(['\n', '\r']);
+            if line.contains(&self.search_text) {
+                found = true;
+                self.out.set_line((name, line_number, line));
+            }
+        }
+
+        self.num_searched += 1;
+        if found {
+            self.num_found += 1;
+        }
+
+        Ok(())
+    }
+
+    /// Keep track of searched files so lines appended to them later can be
+    /// reported by `poll_followed`
+    pub fn set_follow(&mut self) {
+        self.follower = Some(Follower::new());
+    }
+
+    /// Report lines appended to followed files since the last poll that
+    /// contain the search text. Files passed to `do_file` from now on are
+    /// followed from their start instead of being searched.
+    pub fn poll_followed(&mut self) {
+        self.following = true;
+
+        let search_text = &self.search_text;
+        let out = &mut self.out;
+        if let Some(follower) = &mut self.follower {
+            follower.poll(&*self.fs, |path, line_number, line| {
+                if line.contains(search_text.as_str()) {
+                    out.set_line((path, line_number, line));
+                }
+            });
+        }
+    }
+
+    /// Keep the results in memory so repeated walks only search changed
+    /// files and report hits that appeared or went away
+    pub fn set_watch(&mut self) {
+        self.watcher = Some(Watcher::new());
+    }
+
+    /// End a pass of repeated searches, hits in files deleted since the
+    /// previous pass are reported as resolved
+    pub fn end_watch_pass(&mut self) {
+        let fs = self.fs.clone();
+        if let Some(watcher) = &mut self.watcher {
+            let report = watcher.get_pass() > 0;
+            for path in watcher.end_pass(|path| fs.metadata(path).is_ok()) {
+                if report {
+                    self.out.set_change((&path, false));
+                }
+            }
+        }
+    }
+
+    /// Take the results of unchanged files from `cache` instead of
+    /// searching them, the results of this run are recorded in it
+    pub fn set_cache(&mut self, cache: ResultCache) {
+        self.cache = Some(cache);
+    }
+
+    /// Get the result cache with the results recorded so far
+    pub fn get_cache(&self) -> Option<&ResultCache> {
+        self.cache.as_ref()
+    }
+
+    /// Key of the current query in the result cache, it holds everything
+    /// that decides whether the text is found in a given file
+    fn cache_query(&self) -> String {
+        format!("{}\0decompress={}", self.search_text, self.decompress)
+    }
+
+    /// Returns the number of files being followed
+    pub fn get_num_followed(&self) -> usize {
+        self.follower.as_ref().map_or(0, |f| f.len())
+    }
+
+    /// Stop the walk once `max` files containing the search text are found
+    pub fn set_max_found(&mut self, max: usize) {
+        self.max_found = Some(max);
+    }
 }
 
 impl<T: SearchEvent> Default for TextSearch<T> {
@@ -77,6 +358,57 @@ impl<T: SearchEvent> Default for TextSearch<T> {
     }
 }
 
+/// Check whether a file starts with the magic bytes of a compression format
+fn is_compressed(fs: &dyn FileSystem, path: &Path) -> bool {
+    let mut head = Vec::with_capacity(6);
+    fs.open(path)
+        .and_then(|file| file.take(6).read_to_end(&mut head))
+        .is_ok_and(|_| Compression::detect(&head).is_some())
+}
+
+/// Size of the chunks decompressed contents are searched in
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// Search a stream chunk by chunk, the last `needle.len() - 1` bytes of a
+/// chunk are kept in front of the next one so a match across the boundary
+/// is found. Reading stops at the first match. Contents whose first chunk
+/// is not UTF-8 are `InvalidData`, like plain files that are not text.
+/// Returns whether the needle was found and the number of bytes read.
+fn search_chunks(mut reader: impl Read, needle: &[u8]) -> std::io::Result<(bool, u64)> {
+    let keep = needle.len().saturating_sub(1);
+    let mut buffer = vec![0u8; keep + CHUNK_SIZE];
+    let mut kept = 0;
+    let mut bytes_read = 0u64;
+
+    loop {
+        let bytes = match reader.read(&mut buffer[kept..kept + CHUNK_SIZE]) {
+            Ok(0) => return Ok((needle.is_empty(), bytes_read)),
+            Ok(bytes) => bytes,
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+
+        // a character cut off at the end of the chunk is not an error
+        if bytes_read == 0 {
+            if let Err(e) = std::str::from_utf8(&buffer[..bytes]) {
+                if e.error_len().is_some() {
+                    return Err(std::io::ErrorKind::InvalidData.into());
+                }
+            }
+        }
+        bytes_read += bytes as u64;
+
+        let data = &buffer[..kept + bytes];
+        if needle.is_empty() || data.windows(needle.len()).any(|w| w == needle) {
+            return Ok((true, bytes_read));
+        }
+
+        let start = data.len().saturating_sub(keep);
+        buffer.copy_within(start..kept + bytes, 0);
+        kept = kept + bytes - start;
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -85,6 +417,8 @@ mod tests {
         dir: PathBuf,
         file: PathBuf,
         search_text: String,
+        lines: Vec<(usize, String)>,
+        changes: Vec<(PathBuf, bool)>,
     }
 
     impl SearchEvent for MockOutput {
@@ -93,6 +427,8 @@ mod tests {
                 dir: PathBuf::new(),
                 file: PathBuf::new(),
                 search_text: String::new(),
+                lines: Vec::new(),
+                changes: Vec::new(),
             }
         }
 
@@ -104,6 +440,14 @@ mod tests {
             self.file = PathBuf::from(result.0);
             self.search_text = (result.2).to_string();
         }
+
+        fn set_line(&mut self, result: (&Path, usize, &str)) {
+            self.lines.push((result.1, result.2.to_string()));
+        }
+
+        fn set_change(&mut self, result: (&Path, bool)) {
+            self.changes.push((result.0.to_path_buf(), result.1));
+        }
     }
 
     #[test]
@@ -134,4 +478,317 @@ mod tests {
         assert_eq!(app.out.file, search_file);
         assert_eq!(app.out.search_text, search_text.to_string());
     }
+
+    #[test]
+    fn test_max_found() {
+        let mut app = TextSearch::<MockOutput>::new();
+        let search_path = Path::new("./src");
+        let search_file = Path::new("text_search.rs");
+
+        app.set_search_text("SearchEvent");
+        app.set_max_found(1);
+        app.do_dir(search_path);
+        assert!(!app.done());
+
+        app.do_file(search_file);
+        assert_eq!(app.get_num_found(), 1);
+        assert!(app.done());
+    }
+
+    #[test]
+    fn test_stats() {
+        let mut app = TextSearch::<MockOutput>::new();
+        let search_path = Path::new("./src");
+
+        app.set_search_text("SearchEvent");
+        app.do_dir(search_path);
+        app.do_file(Path::new("text_search.rs"));
+        app.do_file(Path::new("does_not_exist.rs"));
+
+        let mut stats = TraversalStats::default();
+        app.add_stats(&mut stats);
+
+        assert_eq!(stats.files_searched, 1);
+        assert_eq!(stats.files_found, 1);
+        assert_eq!(stats.errors, 1);
+        assert!(stats.bytes_read > 0);
+    }
+
+    #[test]
+    fn test_file_system() {
+        let mut fs = dir_nav::MemoryFileSystem::new();
+        fs.add_file(Path::new("logs/app.log"), b"started\nSearchEvent\n");
+        fs.add_file(Path::new("logs/binary.log"), &[0xff, 0xfe, 0x00]);
+
+        let mut app = TextSearch::<MockOutput>::new();
+        app.set_file_system(Arc::new(fs));
+        app.set_search_text("SearchEvent");
+        app.do_dir(Path::new("logs"));
+        app.do_file(Path::new("app.log"));
+        app.do_file(Path::new("binary.log"));
+
+        let mut stats = TraversalStats::default();
+        app.add_stats(&mut stats);
+
+        assert_eq!(app.get_num_found(), 1);
+        assert_eq!(stats.files_binary, 1);
+        assert_eq!(stats.bytes_read, 20);
+    }
+
+    #[test]
+    fn test_decompress() {
+        let mut gz = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        gz.write_all(b"rotated SearchEvent\n").unwrap();
+
+        let mut fs = dir_nav::MemoryFileSystem::new();
+        fs.add_file(Path::new("logs/app.log.1"), &gz.finish().unwrap());
+
+        let mut app = TextSearch::<MockOutput>::new();
+        app.set_file_system(Arc::new(fs));
+        app.set_search_text("SearchEvent");
+        app.do_dir(Path::new("logs"));
+        app.do_file(Path::new("app.log.1"));
+        assert_eq!(app.get_num_found(), 1);
+
+        // raw gzip is not valid UTF-8
+        app.set_decompress(false);
+        app.do_file(Path::new("app.log.1"));
+        assert_eq!(app.get_num_found(), 1);
+        assert_eq!(app.num_binary, 1);
+    }
+
+    #[test]
+    fn test_search_chunks() {
+        // a match across two chunks, and a byte that is not UTF-8 after the
+        // first chunk
+        let mut contents = vec![b'x'; CHUNK_SIZE - 3];
+        contents.extend_from_slice(b"SearchEvent");
+        contents.push(0xff);
+        let found = search_chunks(std::io::Cursor::new(&contents), b"SearchEvent").unwrap();
+        assert_eq!(found, (true, contents.len() as u64));
+
+        let missing = search_chunks(std::io::Cursor::new(&contents), b"Other").unwrap();
+        assert_eq!(missing, (false, contents.len() as u64));
+
+        let binary = search_chunks(std::io::Cursor::new(b"\xff\xfe"), b"x").unwrap_err();
+        assert_eq!(binary.kind(), std::io::ErrorKind::InvalidData);
+
+        // decompressed files go through the chunks
+        let mut gz = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        gz.write_all(&contents).unwrap();
+        let mut fs = dir_nav::MemoryFileSystem::new();
+        fs.add_file(Path::new("logs/app.log.1"), &gz.finish().unwrap());
+
+        let mut app = TextSearch::<MockOutput>::new();
+        app.set_file_system(Arc::new(fs));
+        app.set_search_text("SearchEvent");
+        app.do_dir(Path::new("logs"));
+        app.do_file(Path::new("app.log.1"));
+        assert_eq!((app.get_num_found(), app.num_binary), (1, 0));
+    }
+
+    /// Filesystem whose files fail to read like a stream decompressed past
+    /// the limit
+    struct TooLarge;
+
+    impl Read for TooLarge {
+        fn read(&mut self, _buf: &mut [u8]) -> std::io::Result<usize> {
+            Err(std::io::ErrorKind::FileTooLarge.into())
+        }
+    }
+
+    impl FileSystem for TooLarge {
+        fn read_dir(&self, _dir: &Path) -> std::io::Result<Vec<std::ffi::OsString>> {
+            Ok(Vec::new())
+        }
+
+        fn metadata(&self, _path: &Path) -> std::io::Result<dir_nav::EntryMetadata> {
+            Err(std::io::ErrorKind::NotFound.into())
+        }
+
+        fn open(&self, _path: &Path) -> std::io::Result<Box<dyn Read + Send>> {
+            Ok(Box::new(TooLarge))
+        }
+    }
+
+    #[test]
+    fn test_too_big() {
+        let mut app = TextSearch::<MockOutput>::new();
+        app.set_file_system(Arc::new(TooLarge));
+        app.set_search_text("SearchEvent");
+        app.do_dir(Path::new("logs"));
+        app.do_file(Path::new("app.log.gz"));
+        app.set_decompress(false);
+        app.do_file(Path::new("app.log"));
+
+        let mut stats = TraversalStats::default();
+        app.add_stats(&mut stats);
+
+        assert_eq!(stats.files_too_big, 2);
+        assert_eq!(stats.errors, 0);
+    }
+
+    #[test]
+    fn test_search_lines() {
+        let mut app = TextSearch::<MockOutput>::new();
+        app.set_search_text("ERROR");
+
+        let input = "INFO start\nERROR disk full\r\nINFO retry\nERROR gave up";
+        app.search_lines(Path::new(""), input.as_bytes()).unwrap();
+
+        assert_eq!(
+            app.out.lines,
+            vec![
+                (2, "ERROR disk full".to_string()),
+                (4, "ERROR gave up".to_string())
+            ]
+        );
+        assert_eq!(app.get_num_found(), 1);
+        assert_eq!(app.bytes_read, input.len() as u64);
+    }
+
+    #[test]
+    fn test_follow() {
+        let mut fs = dir_nav::MemoryFileSystem::new();
+        fs.add_file(Path::new("logs/app.log"), b"SearchEvent one\n");
+        let mut gz = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        gz.write_all(b"SearchEvent old\n").unwrap();
+        fs.add_file(Path::new("logs/app.log.1.gz"), &gz.finish().unwrap());
+
+        // compressed files are searched but not followed
+        let mut app = TextSearch::<MockOutput>::new();
+        app.set_file_system(Arc::new(fs.clone()));
+        app.set_search_text("SearchEvent");
+        app.set_follow();
+        app.do_dir(Path::new("logs"));
+        app.do_file(Path::new("app.log"));
+        app.do_file(Path::new("app.log.1.gz"));
+        assert_eq!(app.get_num_found(), 2);
+        assert_eq!(app.get_num_followed(), 1);
+
+        fs.add_file(
+            Path::new("logs/app.log"),
+            b"SearchEvent one\nother\nSearchEvent two\n",
+        );
+        fs.add_file(Path::new("logs/new.log"), b"SearchEvent new\n");
+        app.set_file_system(Arc::new(fs));
+
+        // known files are skipped, new ones are read on the next poll,
+        // compressed ones never
+        app.poll_followed();
+        app.do_file(Path::new("app.log"));
+        app.do_file(Path::new("new.log"));
+        app.do_file(Path::new("app.log.1.gz"));
+        assert_eq!(app.get_num_followed(), 2);
+        assert_eq!(app.out.lines, vec![(3, "SearchEvent two".to_string())]);
+
+        app.poll_followed();
+        assert_eq!(app.out.lines.len(), 2);
+        assert_eq!(app.out.lines[1], (1, "SearchEvent new".to_string()));
+    }
+
+    #[test]
+    fn test_watch() {
+        let mut fs = dir_nav::MemoryFileSystem::new();
+        fs.add_file(Path::new("src/a.rs"), b"SearchEvent");
+        fs.add_file(Path::new("src/b.rs"), b"nothing");
+
+        let mut app = TextSearch::<MockOutput>::new();
+        app.set_search_text("SearchEvent");
+        app.set_watch();
+
+        let pass = |app: &mut TextSearch<MockOutput>, fs: &dir_nav::MemoryFileSystem| {
+            app.set_file_system(Arc::new(fs.clone()));
+            app.do_dir(Path::new("src"));
+            for name in fs.read_dir(Path::new("src")).unwrap() {
+                app.do_file(Path::new(&name));
+            }
+            app.end_watch_pass();
+        };
+
+        // a pass cut short does not resolve the hits it did not get to
+        let partial = |app: &mut TextSearch<MockOutput>| {
+            app.do_dir(Path::new("src"));
+            app.end_watch_pass();
+        };
+
+        pass(&mut app, &fs);
+        assert!(app.out.changes.is_empty());
+        let searched = app.num_searched;
+
+        // unchanged files are not read again
+        pass(&mut app, &fs);
+        assert_eq!(app.num_searched, searched);
+
+        partial(&mut app);
+        assert!(app.out.changes.is_empty());
+
+        fs.add_file(Path::new("src/b.rs"), b"now SearchEvent");
+        fs.add_file(Path::new("src/c.rs"), b"SearchEvent too");
+        fs.remove(Path::new("src/a.rs"));
+        pass(&mut app, &fs);
+
+        assert_eq!(
+            app.out.changes,
+            vec![
+                (PathBuf::from("src/b.rs"), true),
+                (PathBuf::from("src/c.rs"), true),
+                (PathBuf::from("src/a.rs"), false)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_cache() {
+        let set_file = |fs: &mut dir_nav::MemoryFileSystem, path: &str, contents: &[u8], secs| {
+            fs.add_file(Path::new(path), contents);
+            let mut metadata =
+                dir_nav::EntryMetadata::new(dir_nav::EntryKind::File, contents.len() as u64);
+            metadata.modified =
+                std::time::SystemTime::UNIX_EPOCH.checked_add(std::time::Duration::new(secs, 0));
+            fs.set_metadata(Path::new(path), metadata).unwrap();
+        };
+
+        let run = |fs: &dir_nav::MemoryFileSystem, cache: ResultCache| {
+            let mut app = TextSearch::<MockOutput>::new();
+            app.set_file_system(Arc::new(fs.clone()));
+            app.set_search_text("SearchEvent");
+            app.set_cache(cache);
+            app.do_dir(Path::new("src"));
+            for name in fs.read_dir(Path::new("src")).unwrap() {
+                app.do_file(Path::new(&name));
+            }
+            app
+        };
+
+        let mut fs = dir_nav::MemoryFileSystem::new();
+        set_file(&mut fs, "src/a.rs", b"SearchEvent", 1);
+        set_file(&mut fs, "src/b.rs", b"nothing", 1);
+        set_file(&mut fs, "src/c.rs", b"SearchEvent", 1);
+
+        let app = run(&fs, ResultCache::new());
+        assert_eq!((app.num_searched, app.num_found, app.num_cached), (3, 2, 0));
+
+        let path = std::env::temp_dir().join(format!(
+            "textfinder-cache-test-cache-{}",
+            std::process::id()
+        ));
+        app.get_cache().unwrap().write_to(&path).unwrap();
+        let cache = ResultCache::read_from(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        // only the changed file is searched, the others are replayed
+        set_file(&mut fs, "src/b.rs", b"SearchEvent", 2);
+        let app = run(&fs, cache.clone());
+        assert_eq!((app.num_searched, app.num_found, app.num_cached), (1, 3, 2));
+
+        // another text does not use the results of the first one
+        let mut other = TextSearch::<MockOutput>::new();
+        other.set_search_text("nothing");
+        other.set_cache(cache);
+        other.set_file_system(Arc::new(fs.clone()));
+        other.do_dir(Path::new("src"));
+        other.do_file(Path::new("a.rs"));
+        assert_eq!((other.num_searched, other.num_cached), (1, 0));
+    }
 }
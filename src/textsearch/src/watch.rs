@@ -0,0 +1,139 @@
+use dir_nav::EntryMetadata;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// Last known state of a searched file
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct WatchedFile {
+    len: u64,
+    modified: Option<SystemTime>,
+    /// Search text was found in the file
+    found: bool,
+    /// Pass in which the file was last seen
+    pass: u64,
+}
+
+/// Result set of repeated searches over the same tree, files whose size
+/// and modification time did not change since the last pass are not
+/// searched again and changes of the hits between passes are reported
+#[derive(Debug, Default)]
+pub struct Watcher {
+    files: BTreeMap<PathBuf, WatchedFile>,
+    pass: u64,
+}
+
+impl Watcher {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Number of passes finished so far
+    pub fn get_pass(&self) -> u64 {
+        self.pass
+    }
+
+    /// Paths of all files containing the search text
+    pub fn hits(&self) -> impl Iterator<Item = &PathBuf> {
+        self.files.iter().filter(|(_, f)| f.found).map(|(p, _)| p)
+    }
+
+    /// Check whether the file is unchanged since it was last searched, it is
+    /// marked as seen in the current pass if so
+    pub fn is_unchanged(&mut self, path: &Path, metadata: &EntryMetadata) -> bool {
+        let pass = self.pass;
+        match self.files.get_mut(path) {
+            Some(file) if file.len == metadata.len && file.modified == metadata.modified => {
+                file.pass = pass;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Record the result of searching a file, returns `Some(true)` for a new
+    /// hit and `Some(false)` for a resolved one
+    pub fn record(&mut self, path: &Path, metadata: &EntryMetadata, found: bool) -> Option<bool> {
+        let file = WatchedFile {
+            len: metadata.len,
+            modified: metadata.modified,
+            found,
+            pass: self.pass,
+        };
+
+        let was_found = self
+            .files
+            .insert(path.to_path_buf(), file)
+            .is_some_and(|f| f.found);
+
+        if found != was_found {
+            Some(found)
+        } else {
+            None
+        }
+    }
+
+    /// End the current pass, files not seen during it that `exists` no
+    /// longer finds were deleted and the ones among them that were hits are
+    /// returned as resolved. Files that were not seen for another reason,
+    /// e.g. a read error or a walk cut short, keep their last result.
+    pub fn end_pass(&mut self, exists: impl Fn(&Path) -> bool) -> Vec<PathBuf> {
+        let pass = self.pass;
+        let mut resolved = Vec::new();
+
+        self.files.retain(|path, file| {
+            if file.pass == pass || exists(path) {
+                return true;
+            }
+            if file.found {
+                resolved.push(path.clone());
+            }
+            false
+        });
+
+        self.pass += 1;
+        resolved
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dir_nav::EntryKind;
+    use std::time::Duration;
+
+    fn metadata(len: u64, secs: u64) -> EntryMetadata {
+        let mut metadata = EntryMetadata::new(EntryKind::File, len);
+        metadata.modified = SystemTime::UNIX_EPOCH.checked_add(Duration::from_secs(secs));
+        metadata
+    }
+
+    #[test]
+    fn test_watcher() {
+        let mut watcher = Watcher::new();
+        let (a, b) = (Path::new("a.rs"), Path::new("b.rs"));
+
+        assert!(!watcher.is_unchanged(a, &metadata(10, 1)));
+        assert_eq!(watcher.record(a, &metadata(10, 1), true), Some(true));
+        assert_eq!(watcher.record(b, &metadata(5, 1), false), None);
+        assert!(watcher.end_pass(|_| false).is_empty());
+
+        // a is unchanged, b changed and now has a hit
+        assert!(watcher.is_unchanged(a, &metadata(10, 1)));
+        assert!(!watcher.is_unchanged(b, &metadata(5, 2)));
+        assert_eq!(watcher.record(b, &metadata(5, 2), true), Some(true));
+        assert!(watcher.end_pass(|_| false).is_empty());
+        assert_eq!(watcher.hits().count(), 2);
+
+        // a was not seen but still exists, it keeps its hit
+        assert!(watcher.is_unchanged(b, &metadata(5, 2)));
+        assert!(watcher.end_pass(|path| path == a).is_empty());
+        assert_eq!(watcher.hits().count(), 2);
+
+        // a was deleted, b lost its hit
+        assert_eq!(watcher.record(b, &metadata(6, 3), false), Some(false));
+        assert_eq!(watcher.end_pass(|_| false), vec![PathBuf::from("a.rs")]);
+        assert_eq!(watcher.hits().count(), 0);
+        assert_eq!(watcher.get_pass(), 4);
+    }
+}